@@ -0,0 +1,314 @@
+// Ages such as 36h, 2w, 6mo or 1y, absolute dates, and the window of
+// timestamps they select.
+use chrono::{DateTime, Duration, Local, Months, NaiveDate, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::time::SystemTime;
+
+// How dates are given on the command line and shown.
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+const DATE_TIME_FORMATS: [&str; 2] = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AgeUnit {
+    Seconds,
+    Minutes,
+    Hours,
+    Days,
+    Weeks,
+    Months,
+    Years,
+}
+
+const UNITS: [(&str, AgeUnit); 7] = [
+    ("s", AgeUnit::Seconds),
+    ("min", AgeUnit::Minutes),
+    ("h", AgeUnit::Hours),
+    ("d", AgeUnit::Days),
+    ("w", AgeUnit::Weeks),
+    ("mo", AgeUnit::Months),
+    ("y", AgeUnit::Years),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Age {
+    amount: u32,
+    unit: AgeUnit,
+    // A bare number, which counts days for compatibility with older versions.
+    bare: bool,
+}
+
+impl Age {
+    // The moment this long before `now`. Months and years are calendar
+    // months and years, so 1mo before March 31st is the end of February.
+    pub fn before(&self, now: DateTime<Local>) -> DateTime<Local> {
+        let amount = i64::from(self.amount);
+        let earlier = match self.unit {
+            AgeUnit::Seconds => now.checked_sub_signed(Duration::seconds(amount)),
+            AgeUnit::Minutes => now.checked_sub_signed(Duration::minutes(amount)),
+            AgeUnit::Hours => now.checked_sub_signed(Duration::hours(amount)),
+            AgeUnit::Days => now.checked_sub_signed(Duration::days(amount)),
+            AgeUnit::Weeks => now.checked_sub_signed(Duration::weeks(amount)),
+            AgeUnit::Months => now.checked_sub_months(Months::new(self.amount)),
+            AgeUnit::Years => now.checked_sub_months(Months::new(self.amount.saturating_mul(12))),
+        };
+        earlier.unwrap_or(DateTime::<Local>::MIN_UTC.into())
+    }
+
+    // The moment something stamped `time` becomes this old. Calendar months
+    // make `before` lossy, 1mo after January 31st being the end of February,
+    // so the result is moved on a day at a time until `before` agrees.
+    pub fn after(&self, time: DateTime<Local>) -> DateTime<Local> {
+        let amount = i64::from(self.amount);
+        let later = match self.unit {
+            AgeUnit::Seconds => time.checked_add_signed(Duration::seconds(amount)),
+            AgeUnit::Minutes => time.checked_add_signed(Duration::minutes(amount)),
+            AgeUnit::Hours => time.checked_add_signed(Duration::hours(amount)),
+            AgeUnit::Days => time.checked_add_signed(Duration::days(amount)),
+            AgeUnit::Weeks => time.checked_add_signed(Duration::weeks(amount)),
+            AgeUnit::Months => time.checked_add_months(Months::new(self.amount)),
+            AgeUnit::Years => time.checked_add_months(Months::new(self.amount.saturating_mul(12))),
+        };
+        let mut later = later.unwrap_or(DateTime::<Local>::MAX_UTC.into());
+        while self.before(later) < time {
+            match later.checked_add_signed(Duration::days(1)) {
+                Some(next) => later = next,
+                None => break,
+            }
+        }
+        later
+    }
+
+    pub fn days(days: u32) -> Age {
+        Age {
+            amount: days,
+            unit: AgeUnit::Days,
+            bare: true,
+        }
+    }
+}
+
+impl fmt::Display for Age {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.bare {
+            return write!(f, "{} days", self.amount);
+        }
+        let unit = UNITS
+            .iter()
+            .find(|(_, unit)| *unit == self.unit)
+            .map(|(suffix, _)| *suffix)
+            .unwrap_or_default();
+        write!(f, "{}{}", self.amount, unit)
+    }
+}
+
+// Parses 36h, 2w, 6mo, 1y and so on. A bare number is a number of days.
+pub fn parse_age(value: &str) -> Result<Age, String> {
+    let value = value.trim();
+    let number_end = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, suffix) = value.split_at(number_end);
+    let amount = number
+        .parse()
+        .map_err(|_| format!("'{}' is not an age such as 30, 36h, 2w, 6mo or 1y", value))?;
+    if suffix.is_empty() {
+        return Ok(Age::days(amount));
+    }
+    let suffix = suffix.trim().to_lowercase();
+    let unit = UNITS
+        .iter()
+        .find(|(name, _)| *name == suffix)
+        .map(|(_, unit)| *unit)
+        .ok_or_else(|| format!("Unknown unit in '{}', use s, min, h, d, w, mo or y", value))?;
+    Ok(Age {
+        amount,
+        unit,
+        bare: false,
+    })
+}
+
+// Parses 2024-01-01 (local midnight), 2024-01-01 12:00, or RFC 3339.
+pub fn parse_date(value: &str) -> Result<DateTime<Local>, String> {
+    let value = value.trim();
+    let invalid = || format!("'{}' is not a date such as 2024-01-01", value);
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date_time.with_timezone(&Local));
+    }
+    let naive = DATE_TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, DATE_FORMAT)
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+        .ok_or_else(invalid)?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(invalid)
+}
+
+// Accepts a number of days or an age string in the config file.
+pub fn deserialize_age<'de, D>(deserializer: D) -> Result<Option<Age>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawAge {
+        Days(u32),
+        Text(String),
+    }
+    match Option::<RawAge>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(RawAge::Days(days)) => Ok(Some(Age::days(days))),
+        Some(RawAge::Text(text)) => parse_age(&text).map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+// The range of timestamps a run removes files from. Every bound that is set
+// must hold.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimeWindow {
+    pub older_than: Option<Age>,
+    pub newer_than: Option<Age>,
+    pub before: Option<DateTime<Local>>,
+    pub after: Option<DateTime<Local>>,
+}
+
+// The window with its bounds resolved against the start of the run.
+#[derive(Debug, Clone, Copy)]
+pub struct Cutoffs {
+    latest: Option<DateTime<Local>>,
+    earliest: Option<DateTime<Local>>,
+}
+
+impl TimeWindow {
+    pub fn cutoffs(&self, now: DateTime<Local>) -> Cutoffs {
+        let latest = [self.older_than.map(|age| age.before(now)), self.before]
+            .into_iter()
+            .flatten()
+            .min();
+        let earliest = [self.newer_than.map(|age| age.before(now)), self.after]
+            .into_iter()
+            .flatten()
+            .max();
+        Cutoffs { latest, earliest }
+    }
+
+    pub fn describe(&self) -> String {
+        let parts: Vec<String> = [
+            self.older_than.map(|age| format!("older than {}", age)),
+            self.newer_than.map(|age| format!("newer than {}", age)),
+            self.before
+                .map(|date| format!("before {}", date.format(DATE_FORMAT))),
+            self.after
+                .map(|date| format!("after {}", date.format(DATE_FORMAT))),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if parts.is_empty() {
+            "any age".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+impl Cutoffs {
+    pub fn contains(&self, time: SystemTime) -> bool {
+        let time = DateTime::<Local>::from(time);
+        self.latest.is_none_or(|latest| time <= latest)
+            && self.earliest.is_none_or(|earliest| time >= earliest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(year: i32, month: u32, day: u32, hour: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(year, month, day, hour, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn parse_age_reads_units_and_bare_days() {
+        let age = parse_age("36h").unwrap();
+        assert_eq!(
+            (age.amount, age.unit, age.bare),
+            (36, AgeUnit::Hours, false)
+        );
+        let age = parse_age(" 6MO ").unwrap();
+        assert_eq!((age.amount, age.unit), (6, AgeUnit::Months));
+        assert_eq!(parse_age("30").unwrap(), Age::days(30));
+        assert_eq!(parse_age("2w").unwrap().to_string(), "2w");
+        assert_eq!(parse_age("30").unwrap().to_string(), "30 days");
+    }
+
+    #[test]
+    fn parse_age_rejects_bad_values() {
+        assert!(parse_age("").is_err());
+        assert!(parse_age("w").is_err());
+        assert!(parse_age("3q").is_err());
+        assert!(parse_age("-3d").is_err());
+    }
+
+    #[test]
+    fn before_uses_calendar_months() {
+        let age = parse_age("1mo").unwrap();
+        assert_eq!(age.before(local(2024, 3, 31, 12)), local(2024, 2, 29, 12));
+        let age = parse_age("1y").unwrap();
+        assert_eq!(age.before(local(2024, 2, 29, 12)), local(2023, 2, 28, 12));
+    }
+
+    #[test]
+    fn after_moves_past_short_months() {
+        let age = parse_age("1mo").unwrap();
+        // January 31st plus a month lands on February 29th, but a month
+        // before that is January 29th, so the file is only a month old on
+        // March 1st.
+        let after = age.after(local(2024, 1, 31, 12));
+        assert_eq!(after, local(2024, 3, 1, 12));
+        assert!(age.before(after) >= local(2024, 1, 31, 12));
+        assert_eq!(age.after(local(2024, 1, 15, 12)), local(2024, 2, 15, 12));
+        let age = parse_age("36h").unwrap();
+        assert_eq!(age.after(local(2024, 1, 1, 0)), local(2024, 1, 2, 12));
+    }
+
+    #[test]
+    fn cutoffs_contain_times_inside_the_window() {
+        let now = local(2024, 6, 15, 12);
+        let window = TimeWindow {
+            older_than: Some(parse_age("7d").unwrap()),
+            newer_than: Some(parse_age("30d").unwrap()),
+            ..TimeWindow::default()
+        };
+        let cutoffs = window.cutoffs(now);
+        assert!(cutoffs.contains(local(2024, 6, 1, 12).into()));
+        assert!(cutoffs.contains(local(2024, 6, 8, 12).into()));
+        assert!(!cutoffs.contains(local(2024, 6, 10, 12).into()));
+        assert!(!cutoffs.contains(local(2024, 5, 1, 12).into()));
+    }
+
+    #[test]
+    fn cutoffs_take_the_narrowest_bounds() {
+        let window = TimeWindow {
+            older_than: Some(parse_age("1d").unwrap()),
+            before: Some(parse_date("2024-01-01").unwrap()),
+            after: Some(parse_date("2023-01-01").unwrap()),
+            ..TimeWindow::default()
+        };
+        let cutoffs = window.cutoffs(local(2024, 6, 15, 12));
+        assert!(cutoffs.contains(local(2023, 6, 1, 12).into()));
+        assert!(!cutoffs.contains(local(2024, 3, 1, 12).into()));
+        assert!(!cutoffs.contains(local(2022, 12, 31, 12).into()));
+        assert!(TimeWindow::default()
+            .cutoffs(local(2024, 6, 15, 12))
+            .contains(local(2030, 1, 1, 12).into()));
+    }
+}
@@ -0,0 +1,172 @@
+// Packs files into a dated, compressed tarball before they are removed.
+use chrono::Local;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+const ARCHIVE_DATE_FORMAT: &str = "%Y-%m-%d";
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ArchiveFormat {
+    Gz,
+    Zst,
+}
+
+impl ArchiveFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Gz => "tar.gz",
+            ArchiveFormat::Zst => "tar.zst",
+        }
+    }
+
+    fn from_path(path: &Path) -> io::Result<ArchiveFormat> {
+        let name = path.to_string_lossy();
+        if name.ends_with(ArchiveFormat::Zst.extension()) {
+            Ok(ArchiveFormat::Zst)
+        } else if name.ends_with(ArchiveFormat::Gz.extension()) {
+            Ok(ArchiveFormat::Gz)
+        } else {
+            Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} is not a clndir archive", path.display()),
+            ))
+        }
+    }
+}
+
+// Writes `files` (paths relative to `source_dir`) into a new archive inside
+// `archive_dir`, reads it back to check every file made it in, and returns
+// the archive path. Mtimes and permissions are stored in the tar headers.
+pub fn archive_files(
+    source_dir: &Path,
+    files: &[String],
+    archive_dir: &Path,
+    format: ArchiveFormat,
+) -> io::Result<PathBuf> {
+    fs::create_dir_all(archive_dir)?;
+    // Absolute, so the path recorded in the journal works from anywhere.
+    let archive_dir = fs::canonicalize(archive_dir)?;
+    let (archive_path, archive_file) = create_archive_file(&archive_dir, format)?;
+
+    let written = match format {
+        ArchiveFormat::Gz => {
+            let encoder = GzEncoder::new(archive_file, Compression::default());
+            append_files(encoder, source_dir, files)
+                .and_then(|encoder| encoder.finish())
+                .and_then(|file| file.sync_all())
+        }
+        ArchiveFormat::Zst => zstd::Encoder::new(archive_file, ZSTD_LEVEL)
+            .and_then(|encoder| append_files(encoder, source_dir, files))
+            .and_then(|encoder| encoder.finish())
+            .and_then(|file| file.sync_all()),
+    };
+    let checked = written.and_then(|_| verify_archive(&archive_path, source_dir, files, format));
+
+    if let Err(e) = checked {
+        let _ = fs::remove_file(&archive_path);
+        return Err(e);
+    }
+    Ok(archive_path)
+}
+
+// Creates clndir-<date>.tar.gz, adding a counter if that name is taken.
+fn create_archive_file(archive_dir: &Path, format: ArchiveFormat) -> io::Result<(PathBuf, File)> {
+    let date = Local::now().format(ARCHIVE_DATE_FORMAT);
+    for attempt in 0.. {
+        let name = if attempt == 0 {
+            format!("clndir-{}.{}", date, format.extension())
+        } else {
+            format!("clndir-{}-{}.{}", date, attempt, format.extension())
+        };
+        let path = archive_dir.join(name);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+fn append_files<W: Write>(writer: W, source_dir: &Path, files: &[String]) -> io::Result<W> {
+    let mut builder = tar::Builder::new(writer);
+    builder.follow_symlinks(false);
+    for name in files {
+        builder.append_path_with_name(source_dir.join(name), name)?;
+    }
+    builder.into_inner()
+}
+
+// Extracts the single file stored as `name` in the archive to `destination`,
+// restoring its mtime and permissions.
+pub fn extract_file(archive_path: &Path, name: &str, destination: &Path) -> io::Result<()> {
+    let mut archive = open_archive(archive_path, ArchiveFormat::from_path(archive_path)?)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.to_string_lossy() == name {
+            entry.unpack(destination)?;
+            return Ok(());
+        }
+    }
+    Err(io::Error::new(
+        ErrorKind::NotFound,
+        format!("{} is not in {}", name, archive_path.display()),
+    ))
+}
+
+fn open_archive(
+    archive_path: &Path,
+    format: ArchiveFormat,
+) -> io::Result<tar::Archive<Box<dyn Read>>> {
+    let file = File::open(archive_path)?;
+    let reader: Box<dyn Read> = match format {
+        ArchiveFormat::Gz => Box::new(GzDecoder::new(file)),
+        ArchiveFormat::Zst => Box::new(zstd::Decoder::new(file)?),
+    };
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_mtime(true);
+    archive.set_preserve_permissions(true);
+    Ok(archive)
+}
+
+// Reads the archive back and checks each file is present with the same size.
+fn verify_archive(
+    archive_path: &Path,
+    source_dir: &Path,
+    files: &[String],
+    format: ArchiveFormat,
+) -> io::Result<()> {
+    let mut archived_sizes = HashMap::new();
+    let mut archive = open_archive(archive_path, format)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().to_string();
+        // Reading each entry to the end makes the decoder check the data.
+        let size = io::copy(&mut entry, &mut io::sink())?;
+        archived_sizes.insert(path, size);
+    }
+
+    for name in files {
+        let expected = fs::symlink_metadata(source_dir.join(name))?;
+        let expected_size = if expected.file_type().is_symlink() {
+            0
+        } else {
+            expected.len()
+        };
+        match archived_sizes.get(name) {
+            Some(&size) if size == expected_size => {}
+            _ => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{} is missing or incomplete in the archive", name),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,155 @@
+// Protection rules stored in `.clndirignore` files inside the cleaned tree.
+// They use gitignore syntax, and a file is protected when it is matched by
+// the closest ignore file that has an opinion about it, so a nested file can
+// un-ignore with `!` what a parent directory's file ignores.
+use crate::error::Warning;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::cmp::Reverse;
+use std::path::{Path, PathBuf};
+
+pub const IGNORE_FILE_NAME: &str = ".clndirignore";
+
+#[derive(Debug)]
+pub struct IgnoreRules {
+    // Matchers keyed by the directory holding the ignore file, relative to
+    // the cleaned directory.
+    matchers: Vec<(PathBuf, Gitignore)>,
+    root: PathBuf,
+    case_sensitive: bool,
+}
+
+impl IgnoreRules {
+    pub fn new(root: &Path, case_sensitive: bool) -> IgnoreRules {
+        IgnoreRules {
+            matchers: Vec::new(),
+            root: root.to_path_buf(),
+            case_sensitive,
+        }
+    }
+
+    // Reads the ignore file in `relative_dir`. Lines that fail to parse are
+    // skipped so one typo does not drop the whole file, and returned as
+    // warnings.
+    pub fn add_ignore_file(&mut self, relative_dir: &Path) -> Vec<Warning> {
+        let dir = self.root.join(relative_dir);
+        let path = dir.join(IGNORE_FILE_NAME);
+        let warning = |e: ignore::Error| Warning {
+            path: path.display().to_string(),
+            message: e.to_string(),
+        };
+        let mut warnings = Vec::new();
+        let mut builder = GitignoreBuilder::new(&dir);
+        builder.case_insensitive(!self.case_sensitive).ok();
+        if let Some(e) = builder.add(&path) {
+            warnings.push(warning(e));
+        }
+        match builder.build() {
+            Ok(matcher) => self.matchers.push((relative_dir.to_path_buf(), matcher)),
+            Err(e) => warnings.push(warning(e)),
+        }
+        warnings
+    }
+
+    // `relative_path` is the path of a file relative to the cleaned directory.
+    pub fn is_protected(&self, relative_path: &str) -> bool {
+        let relative_path = Path::new(relative_path);
+        if relative_path
+            .file_name()
+            .is_some_and(|name| name == IGNORE_FILE_NAME)
+        {
+            return true;
+        }
+        let path = self.root.join(relative_path);
+        let mut applicable: Vec<&(PathBuf, Gitignore)> = self
+            .matchers
+            .iter()
+            .filter(|(dir, _)| relative_path.starts_with(dir))
+            .collect();
+        applicable.sort_by_key(|(dir, _)| Reverse(dir.components().count()));
+        for (_, matcher) in applicable {
+            match matcher.matched_path_or_any_parents(&path, false) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    // A directory holding the given ignore files, as (directory, contents),
+    // loaded into rules. The directory is removed when `test` returns.
+    fn with_rules(name: &str, files: &[(&str, &str)], test: impl FnOnce(&IgnoreRules)) {
+        let root = env::temp_dir().join(format!("clndir-ignore-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let mut rules = IgnoreRules::new(&root, false);
+        for (dir, contents) in files {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join(IGNORE_FILE_NAME), contents).unwrap();
+            assert!(rules.add_ignore_file(Path::new(dir)).is_empty());
+        }
+        test(&rules);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn anchored_patterns_only_match_at_their_directory() {
+        with_rules("anchored", &[("", "/x\n")], |rules| {
+            assert!(rules.is_protected("x"));
+            assert!(!rules.is_protected("sub/x"));
+        });
+    }
+
+    #[test]
+    fn directory_patterns_protect_what_is_inside() {
+        with_rules("directory", &[("", "build/\n")], |rules| {
+            assert!(rules.is_protected("build/a.o"));
+            assert!(rules.is_protected("sub/build/deeper/a.o"));
+            assert!(!rules.is_protected("build"));
+        });
+    }
+
+    #[test]
+    fn negation_in_a_subdirectory_unprotects() {
+        let files = [("", "*.log\n"), ("sub", "!keep.log\n")];
+        with_rules("negation", &files, |rules| {
+            assert!(rules.is_protected("a.log"));
+            assert!(rules.is_protected("A.LOG"));
+            assert!(rules.is_protected("sub/other.log"));
+            assert!(!rules.is_protected("sub/keep.log"));
+            assert!(!rules.is_protected("keep.txt"));
+        });
+    }
+
+    #[test]
+    fn closest_ignore_file_wins() {
+        let files = [
+            ("", "*.tmp\n"),
+            ("sub", "!*.tmp\n"),
+            ("sub/deeper", "*.tmp\n"),
+        ];
+        with_rules("closest", &files, |rules| {
+            assert!(rules.is_protected("a.tmp"));
+            assert!(!rules.is_protected("sub/a.tmp"));
+            assert!(rules.is_protected("sub/deeper/a.tmp"));
+            assert!(!rules.is_protected("sub/other/a.tmp"));
+        });
+    }
+
+    #[test]
+    fn ignore_files_are_always_protected() {
+        with_rules("self", &[("", ""), ("sub", "!*\n")], |rules| {
+            assert!(rules.is_protected(IGNORE_FILE_NAME));
+            assert!(rules.is_protected("sub/.clndirignore"));
+            assert!(!rules.is_protected("sub/a"));
+        });
+    }
+}
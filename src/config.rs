@@ -0,0 +1,120 @@
+// Named cleanup profiles read from $XDG_CONFIG_HOME/clndir/config.toml.
+//
+// allowed_roots = ["~/Downloads", "/tmp"]
+//
+// [profiles.downloads]
+// dir = "~/Downloads"
+// age = 30
+// skip = ["*.iso"]
+// action = "trash"
+// confirm = "never"
+use clndir::age::{self, Age};
+use clndir::policy::{BrokenSymlinks, Hidden, SymlinkPolicy};
+use clndir::{xdg, Action, TimeField};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::{env, fmt, fs, io};
+
+const CONFIG_FILE: &str = "clndir/config.toml";
+
+// Whether the list of files is shown for confirmation before removal.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfirmMode {
+    Prompt,
+    Never,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub dir: Option<String>,
+    // A number of days, or an age such as "6mo".
+    #[serde(default, deserialize_with = "age::deserialize_age")]
+    pub age: Option<Age>,
+    pub time_field: Option<TimeField>,
+    #[serde(default)]
+    pub skip: Vec<String>,
+    #[serde(default)]
+    pub only: Vec<String>,
+    pub recursive: Option<bool>,
+    pub symlinks: Option<SymlinkPolicy>,
+    pub broken_symlinks: Option<BrokenSymlinks>,
+    pub hidden: Option<Hidden>,
+    pub action: Option<Action>,
+    pub confirm: Option<ConfirmMode>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    // When set, only directories under one of these are cleaned.
+    #[serde(default)]
+    pub allowed_roots: Vec<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    UnknownProfile(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "Cannot read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "Invalid config {}: {}", path.display(), e),
+            ConfigError::UnknownProfile(name) => write!(f, "No profile named '{}' in config", name),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn path() -> io::Result<PathBuf> {
+        Ok(xdg::config_home()?.join(CONFIG_FILE))
+    }
+
+    // A missing config file is the same as one without any profiles.
+    pub fn load() -> Result<Config, ConfigError> {
+        let path = Config::path().map_err(|e| ConfigError::Io(PathBuf::from(CONFIG_FILE), e))?;
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(ConfigError::Io(path, e)),
+        };
+        toml::from_str(&contents).map_err(|e| ConfigError::Parse(path, e))
+    }
+
+    pub fn profile(&self, name: &str) -> Result<&Profile, ConfigError> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| ConfigError::UnknownProfile(name.to_string()))
+    }
+
+    pub fn allowed_roots(&self) -> Vec<PathBuf> {
+        self.allowed_roots
+            .iter()
+            .map(|root| PathBuf::from(expand_home(root)))
+            .collect()
+    }
+}
+
+impl Profile {
+    // The profile's directory with a leading ~ expanded to $HOME.
+    pub fn dir(&self) -> Option<String> {
+        self.dir.as_deref().map(expand_home)
+    }
+}
+
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home, rest),
+        _ => path.to_string(),
+    }
+}
@@ -0,0 +1,223 @@
+// Finds files with identical contents. Files are grouped by size first, so
+// only files that could be equal are hashed, then by their BLAKE3 hash.
+use crate::error::Warning;
+use crate::policy::TimeField;
+use crate::scanner::FileRecord;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+// Which copy of a set of duplicates is kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum KeepPolicy {
+    Oldest,
+    Newest,
+    ShortestName,
+}
+
+// Files with the same contents. `kept` is the copy to keep and `duplicates`
+// the ones that may be removed, as indexes into the scanned files.
+#[derive(Debug)]
+pub struct DuplicateSet {
+    pub kept: usize,
+    pub duplicates: Vec<usize>,
+}
+
+// `removable[i]` says whether files[i] passed the skip rules. Protected
+// files are never offered for removal, and one of them is kept in
+// preference to a removable copy. Files that cannot be read are left out
+// and returned as warnings.
+pub fn find_duplicates(
+    dir: &Path,
+    files: &[FileRecord],
+    removable: &[bool],
+    keep: KeepPolicy,
+    time_field: TimeField,
+) -> (Vec<DuplicateSet>, Vec<Warning>) {
+    let mut by_size: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (index, file) in files.iter().enumerate() {
+        // Empty files are all equal but removing them frees nothing.
+        if file.size > 0 {
+            by_size.entry(file.size).or_default().push(index);
+        }
+    }
+
+    let mut sets = Vec::new();
+    let mut warnings = Vec::new();
+    for (_, same_size) in by_size.into_iter().filter(|(_, group)| group.len() > 1) {
+        let mut by_hash: HashMap<blake3::Hash, Vec<usize>> = HashMap::new();
+        let mut seen_inodes = HashMap::new();
+        for index in same_size {
+            let path = dir.join(&files[index].name);
+            let warning = |e: io::Error| Warning {
+                path: path.display().to_string(),
+                message: e.to_string(),
+            };
+            // Hardlinks to one inode take no extra space, so they are not
+            // duplicates of each other.
+            match path.metadata() {
+                Ok(metadata) => {
+                    if seen_inodes
+                        .insert((metadata.dev(), metadata.ino()), index)
+                        .is_some()
+                    {
+                        continue;
+                    }
+                }
+                Err(e) => {
+                    warnings.push(warning(e));
+                    continue;
+                }
+            }
+            match hash_file(&path) {
+                Ok(hash) => by_hash.entry(hash).or_default().push(index),
+                Err(e) => warnings.push(warning(e)),
+            }
+        }
+        for (_, same_contents) in by_hash.into_iter().filter(|(_, group)| group.len() > 1) {
+            if let Some(set) = pick_kept(same_contents, files, removable, keep, time_field) {
+                sets.push(set);
+            }
+        }
+    }
+    sets.sort_by(|a, b| files[a.kept].name.cmp(&files[b.kept].name));
+    (sets, warnings)
+}
+
+fn pick_kept(
+    mut indexes: Vec<usize>,
+    files: &[FileRecord],
+    removable: &[bool],
+    keep: KeepPolicy,
+    time_field: TimeField,
+) -> Option<DuplicateSet> {
+    // Protected copies sort first, then the policy decides.
+    indexes.sort_by(|&a, &b| {
+        removable[a].cmp(&removable[b]).then_with(|| match keep {
+            KeepPolicy::Oldest => files[a].time(time_field).cmp(&files[b].time(time_field)),
+            KeepPolicy::Newest => files[b].time(time_field).cmp(&files[a].time(time_field)),
+            KeepPolicy::ShortestName => files[a]
+                .name
+                .len()
+                .cmp(&files[b].name.len())
+                .then_with(|| files[a].name.cmp(&files[b].name)),
+        })
+    });
+    let kept = indexes[0];
+    let duplicates: Vec<usize> = indexes[1..]
+        .iter()
+        .copied()
+        .filter(|&index| removable[index])
+        .collect();
+    if duplicates.is_empty() {
+        None
+    } else {
+        Some(DuplicateSet { kept, duplicates })
+    }
+}
+
+fn hash_file(path: &Path) -> io::Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::{DepthRange, FileTypes};
+    use crate::scanner::Scanner;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+    use std::time::{Duration, SystemTime};
+
+    const DAY: Duration = Duration::from_secs(60 * 60 * 24);
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("clndir-dedupe-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, name: &str, contents: &str, days_old: u32) {
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - DAY * days_old)
+            .unwrap();
+    }
+
+    // Scans `dir`, then finds its duplicates, the files named in `protected`
+    // not being removable. Returns the names of each set, kept copy first.
+    fn duplicates(dir: &Path, protected: &[&str], keep: KeepPolicy) -> Vec<Vec<String>> {
+        let scanner = Scanner::new(DepthRange { min: 1, max: 1 }, false, FileTypes::default());
+        let files = scanner.scan(&dir.to_string_lossy()).unwrap().files;
+        let removable: Vec<bool> = files
+            .iter()
+            .map(|file| !protected.contains(&file.name.as_str()))
+            .collect();
+        let (sets, warnings) = find_duplicates(dir, &files, &removable, keep, TimeField::Mtime);
+        assert!(warnings.is_empty());
+        sets.iter()
+            .map(|set| {
+                let mut duplicates: Vec<String> = set
+                    .duplicates
+                    .iter()
+                    .map(|&index| files[index].name.clone())
+                    .collect();
+                duplicates.sort();
+                duplicates.insert(0, files[set.kept].name.clone());
+                duplicates
+            })
+            .collect()
+    }
+
+    #[test]
+    fn protected_copies_are_kept_in_preference() {
+        let dir = test_dir("protected");
+        write(&dir, "old", "same", 40);
+        write(&dir, "mid", "same", 20);
+        write(&dir, "new", "same", 1);
+        write(&dir, "other", "different", 50);
+
+        assert_eq!(
+            duplicates(&dir, &[], KeepPolicy::Oldest),
+            [["old", "mid", "new"]]
+        );
+        assert_eq!(
+            duplicates(&dir, &["new"], KeepPolicy::Oldest),
+            [["new", "mid", "old"]]
+        );
+        // The protected copies that are not kept stay too.
+        assert_eq!(
+            duplicates(&dir, &["new", "mid"], KeepPolicy::Oldest),
+            [["mid", "old"]]
+        );
+        assert!(duplicates(&dir, &["new", "mid", "old"], KeepPolicy::Oldest).is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn hardlinks_to_one_inode_are_not_duplicates() {
+        let dir = test_dir("hardlinks");
+        write(&dir, "a", "same", 40);
+        fs::hard_link(dir.join("a"), dir.join("b")).unwrap();
+        assert!(duplicates(&dir, &[], KeepPolicy::ShortestName).is_empty());
+
+        write(&dir, "copy", "same", 1);
+        let sets = duplicates(&dir, &[], KeepPolicy::ShortestName);
+        assert_eq!(sets.len(), 1);
+        assert_eq!(sets[0].len(), 2);
+        assert_eq!(sets[0][1], "copy");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
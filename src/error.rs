@@ -0,0 +1,110 @@
+// Errors that end a run, each mapped to the exit code documented in the
+// readme so scripts can tell them apart.
+use crate::pattern::PatternError;
+use std::fmt;
+use std::io;
+
+// Files were removed, or the command otherwise succeeded.
+pub const EXIT_SUCCESS: i32 = 0;
+// Bad arguments, config or directories, before anything was removed.
+pub const EXIT_ERROR: i32 = 1;
+// No file matched, so nothing was removed.
+pub const EXIT_NOTHING_TO_DO: i32 = 2;
+// Some files were removed but others could not be.
+pub const EXIT_PARTIAL_FAILURE: i32 = 3;
+// The user declined the list of files.
+pub const EXIT_CANCELLED: i32 = 4;
+
+#[derive(Debug)]
+pub enum ClndirError {
+    // No directory was given and the named environment variable is unset.
+    NoDirectory(String),
+    MissingDir(String),
+    PermissionDenied(String),
+    // What failed, such as a path or "terminal", and the error.
+    Io(String, io::Error),
+    InvalidPattern(PatternError),
+    InvalidArgument(String),
+    Config(String),
+    // A directory the guard refuses to clean, and why.
+    UnsafeDirectory { dir: String, reason: String },
+    // `failed` of the `total` files of the run could not be removed.
+    PartialFailure { failed: usize, total: usize },
+    Cancelled,
+}
+
+impl ClndirError {
+    // Classifies an error met while reading `path`.
+    pub fn from_io(path: &str, e: io::Error) -> ClndirError {
+        match e.kind() {
+            io::ErrorKind::NotFound => ClndirError::MissingDir(path.to_string()),
+            io::ErrorKind::PermissionDenied => ClndirError::PermissionDenied(path.to_string()),
+            _ => ClndirError::Io(path.to_string(), e),
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ClndirError::PartialFailure { .. } => EXIT_PARTIAL_FAILURE,
+            ClndirError::Cancelled => EXIT_CANCELLED,
+            _ => EXIT_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for ClndirError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClndirError::NoDirectory(var) => write!(
+                f,
+                "No directory given and environment variable {} not found",
+                var
+            ),
+            ClndirError::MissingDir(dir) => write!(f, "Directory {} not found", dir),
+            ClndirError::PermissionDenied(dir) => write!(f, "Permission denied reading {}", dir),
+            ClndirError::Io(context, e) => write!(f, "{}: {}", context, e),
+            ClndirError::InvalidPattern(e) => write!(f, "{}", e),
+            ClndirError::InvalidArgument(message) => write!(f, "{}", message),
+            ClndirError::Config(message) => write!(f, "{}", message),
+            ClndirError::UnsafeDirectory { dir, reason } => write!(
+                f,
+                "Refusing to clean {}: {}. Pass --i-know-what-im-doing to clean it anyway",
+                dir, reason
+            ),
+            ClndirError::PartialFailure { failed, total } => {
+                write!(f, "{} of {} File(s) could not be removed", failed, total)
+            }
+            ClndirError::Cancelled => write!(f, "Deletion canceled by user"),
+        }
+    }
+}
+
+// A problem that does not stop the run, such as a file that could not be
+// read, returned for the caller to report.
+#[derive(Debug)]
+pub struct Warning {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error reading {}: {}", self.path, self.message)
+    }
+}
+
+impl std::error::Error for ClndirError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClndirError::Io(_, e) => Some(e),
+            ClndirError::InvalidPattern(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<PatternError> for ClndirError {
+    fn from(e: PatternError) -> ClndirError {
+        ClndirError::InvalidPattern(e)
+    }
+}
@@ -0,0 +1,229 @@
+// Applies a plan: archives the candidates if asked to, removes them, and
+// journals what was done so the run can be restored.
+use crate::archive::{self, ArchiveFormat};
+use crate::journal::{ArchivedAs, Journal, JournalAction};
+use crate::planner::{Candidate, DirectoryPlan, Plan};
+use crate::scanner::FileRecord;
+use crate::trash;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// What happens to each file of the plan.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Delete,
+    Trash,
+    // Only used by dedupe, to point a duplicate at the copy that is kept.
+    #[serde(skip)]
+    Hardlink,
+    #[serde(skip)]
+    Symlink,
+}
+
+// Where and how files are archived before they are removed.
+#[derive(Debug, Clone)]
+pub struct ArchiveTarget {
+    pub dir: PathBuf,
+    pub format: ArchiveFormat,
+}
+
+#[derive(Debug)]
+pub struct RemovedFile {
+    // The file as it was planned.
+    pub file: FileRecord,
+    // Bytes freed, measured when the file was removed.
+    pub size: u64,
+}
+
+#[derive(Debug)]
+pub struct FailedFile {
+    pub name: String,
+    pub error: io::Error,
+}
+
+#[derive(Debug)]
+pub struct DirectoryReport {
+    pub dir: String,
+    pub archived_to: Option<PathBuf>,
+    // Set when the archive could not be written, in which case nothing was
+    // removed from the directory.
+    pub archive_error: Option<io::Error>,
+    pub removed: Vec<RemovedFile>,
+    pub failed: Vec<FailedFile>,
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub action: Action,
+    pub dirs: Vec<DirectoryReport>,
+    // The id to restore the run with, None when nothing was journaled.
+    pub run_id: Option<String>,
+    pub journal_error: Option<io::Error>,
+}
+
+impl Report {
+    pub fn removed_count(&self) -> usize {
+        self.dirs.iter().map(|dir| dir.removed.len()).sum()
+    }
+
+    pub fn bytes_freed(&self) -> u64 {
+        self.dirs
+            .iter()
+            .flat_map(|dir| &dir.removed)
+            .map(|file| file.size)
+            .sum()
+    }
+
+    pub fn failed_count(&self) -> usize {
+        self.dirs
+            .iter()
+            .map(|dir| dir.failed.len() + dir.archive_error.is_some() as usize)
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Executor {
+    action: Action,
+    archive: Option<ArchiveTarget>,
+}
+
+impl Executor {
+    pub fn new(action: Action, archive: Option<ArchiveTarget>) -> Executor {
+        Executor { action, archive }
+    }
+
+    pub fn execute(&self, plan: &Plan) -> Report {
+        let mut journal = Journal::new();
+        let dirs = plan
+            .dirs
+            .iter()
+            .map(|dir| self.archive_and_delete(dir, &mut journal))
+            .collect();
+        let (run_id, journal_error) = match journal.save() {
+            Ok(run_id) => (run_id, None),
+            Err(e) => (None, Some(e)),
+        };
+        Report {
+            action: self.action,
+            dirs,
+            run_id,
+            journal_error,
+        }
+    }
+
+    // Archives the directory's candidates if asked to, then removes them.
+    // Nothing is removed from a directory whose archive could not be written.
+    fn archive_and_delete(&self, dir: &DirectoryPlan, journal: &mut Journal) -> DirectoryReport {
+        let mut report = DirectoryReport {
+            dir: dir.dir.clone(),
+            archived_to: None,
+            archive_error: None,
+            removed: Vec::new(),
+            failed: Vec::new(),
+        };
+        if let (Some(archive), false) = (&self.archive, dir.candidates.is_empty()) {
+            let names: Vec<String> = dir
+                .candidates
+                .iter()
+                .map(|candidate| candidate.file.name.clone())
+                .collect();
+            match archive::archive_files(Path::new(&dir.dir), &names, &archive.dir, archive.format)
+            {
+                Ok(path) => report.archived_to = Some(path),
+                Err(e) => {
+                    report.archive_error = Some(e);
+                    return report;
+                }
+            }
+        }
+        for candidate in &dir.candidates {
+            match self.remove(&dir.dir, candidate, report.archived_to.as_deref(), journal) {
+                Ok(size) => report.removed.push(RemovedFile {
+                    file: candidate.file.clone(),
+                    size,
+                }),
+                Err(error) => report.failed.push(FailedFile {
+                    name: candidate.file.name.clone(),
+                    error,
+                }),
+            }
+        }
+        report
+    }
+
+    // Removes one file and returns its size.
+    fn remove(
+        &self,
+        directory_path: &str,
+        candidate: &Candidate,
+        archive_path: Option<&Path>,
+        journal: &mut Journal,
+    ) -> io::Result<u64> {
+        let file = &candidate.file;
+        let file_path = Path::new(directory_path).join(&file.name);
+        let size = fs::symlink_metadata(&file_path)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        if let Action::Hardlink | Action::Symlink = self.action {
+            let kept = replace_with_link(directory_path, candidate, self.action)?;
+            journal.record_link(&file_path, size, file.modified_time, &kept);
+            return Ok(size);
+        }
+        let trashed_to = match self.action {
+            Action::Trash => Some(trash::move_to_trash(&file_path)?),
+            _ => {
+                fs::remove_file(&file_path)?;
+                None
+            }
+        };
+        journal.record(
+            &file_path,
+            size,
+            file.modified_time,
+            match self.action {
+                Action::Trash => JournalAction::Trashed,
+                _ => JournalAction::Deleted,
+            },
+            trashed_to,
+            archive_path.map(|archive| ArchivedAs {
+                archive: archive.to_path_buf(),
+                name: file.name.clone(),
+            }),
+        );
+        Ok(size)
+    }
+}
+
+// Swaps a duplicate for a link to the copy that is kept, and returns the path
+// of that copy. The link is made under a temporary name and renamed over the
+// file, so it is never missing.
+fn replace_with_link(
+    directory_path: &str,
+    candidate: &Candidate,
+    action: Action,
+) -> io::Result<PathBuf> {
+    let kept = match &candidate.duplicate_of {
+        Some(kept) => Path::new(directory_path).join(kept),
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only duplicates can be replaced by links",
+            ))
+        }
+    };
+    let file_path = Path::new(directory_path).join(&candidate.file.name);
+    let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = file_path.with_file_name(format!(".{}.clndir-link", file_name));
+    match action {
+        Action::Symlink => std::os::unix::fs::symlink(fs::canonicalize(&kept)?, &temporary)?,
+        _ => fs::hard_link(&kept, &temporary)?,
+    }
+    fs::rename(&temporary, &file_path).inspect_err(|_| {
+        let _ = fs::remove_file(&temporary);
+    })?;
+    Ok(kept)
+}
@@ -0,0 +1,33 @@
+// Filesystem capacity and free space from statvfs(3).
+use std::ffi::CString;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy)]
+pub struct FsSpace {
+    pub total: u64,
+    // Space available to unprivileged users, which is what a full disk
+    // means to them.
+    pub available: u64,
+}
+
+pub fn space(path: &Path) -> io::Result<FsSpace> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: c_path is a valid NUL terminated string and stat is only read
+    // after statvfs reports it filled it in.
+    let stat = unsafe {
+        if libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        stat.assume_init()
+    };
+    let fragment_size = stat.f_frsize as u64;
+    Ok(FsSpace {
+        total: stat.f_blocks as u64 * fragment_size,
+        available: stat.f_bavail as u64 * fragment_size,
+    })
+}
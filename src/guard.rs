@@ -0,0 +1,193 @@
+// Refuses to clean directories where removing old files would wreck the
+// system or the user's home, such as / or $HOME picked up from a wrong
+// Downloads variable or a typo in --dir.
+use crate::error::ClndirError;
+use std::env;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+// System directories that are never cleaned. Compared with canonical paths,
+// so links such as /bin -> /usr/bin are covered by their target.
+const DENYLIST: [&str; 32] = [
+    "/",
+    "/bin",
+    "/boot",
+    "/dev",
+    "/efi",
+    "/etc",
+    "/home",
+    "/lib",
+    "/lib32",
+    "/lib64",
+    "/media",
+    "/mnt",
+    "/nix",
+    "/opt",
+    "/proc",
+    "/root",
+    "/run",
+    "/sbin",
+    "/snap",
+    "/srv",
+    "/sys",
+    "/usr",
+    "/usr/bin",
+    "/usr/lib",
+    "/usr/lib32",
+    "/usr/lib64",
+    "/usr/local",
+    "/usr/sbin",
+    "/usr/share",
+    "/var",
+    "/var/lib",
+    "/var/log",
+];
+
+// Checks a directory against the denylist, the home directory, mount points
+// and, when some are given, the roots cleaning is allowed under.
+pub fn check_dir(dir: &str, allowed_roots: &[PathBuf]) -> Result<(), ClndirError> {
+    let path = fs::canonicalize(dir).map_err(|e| ClndirError::from_io(dir, e))?;
+    let refuse = |reason: String| {
+        Err(ClndirError::UnsafeDirectory {
+            dir: dir.to_string(),
+            reason,
+        })
+    };
+
+    if DENYLIST.iter().any(|denied| path == Path::new(denied)) {
+        return refuse(format!("{} is a system directory", path.display()));
+    }
+    let home = env::var_os("HOME").and_then(|home| fs::canonicalize(home).ok());
+    if let Some(home) = home.filter(|home| home.starts_with(&path)) {
+        return refuse(if home == path {
+            "it is the home directory".to_string()
+        } else {
+            format!("it contains the home directory {}", home.display())
+        });
+    }
+    if is_mount_root(&path) {
+        return refuse(format!("{} is the root of a filesystem", path.display()));
+    }
+    // Roots that do not exist cannot hold the directory anyway.
+    let allowed: Vec<PathBuf> = allowed_roots
+        .iter()
+        .filter_map(|root| fs::canonicalize(root).ok())
+        .collect();
+    if !allowed_roots.is_empty() && !allowed.iter().any(|root| path.starts_with(root)) {
+        return refuse("it is not under any of the allowed_roots in config".to_string());
+    }
+    Ok(())
+}
+
+// A directory on another device than its parent is where a filesystem is
+// mounted.
+fn is_mount_root(path: &Path) -> bool {
+    let Some(parent) = path.parent() else {
+        return true;
+    };
+    match (fs::metadata(path), fs::metadata(parent)) {
+        (Ok(metadata), Ok(parent_metadata)) => metadata.dev() != parent_metadata.dev(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use std::process;
+    use std::sync::Mutex;
+
+    // The checks read $HOME, which the tests change.
+    static HOME_LOCK: Mutex<()> = Mutex::new(());
+
+    // Runs `test` in a fresh directory holding home/user, which is $HOME
+    // meanwhile.
+    fn with_home(name: &str, test: impl FnOnce(&Path)) {
+        let _lock = HOME_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let root = env::temp_dir().join(format!("clndir-guard-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("home/user")).unwrap();
+        let root = fs::canonicalize(&root).unwrap();
+        let old_home = env::var_os("HOME");
+        env::set_var("HOME", root.join("home/user"));
+        test(&root);
+        match old_home {
+            Some(home) => env::set_var("HOME", home),
+            None => env::remove_var("HOME"),
+        }
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    fn reason(dir: &Path, allowed_roots: &[PathBuf]) -> Option<String> {
+        match check_dir(&dir.to_string_lossy(), allowed_roots) {
+            Ok(()) => None,
+            Err(ClndirError::UnsafeDirectory { reason, .. }) => Some(reason),
+            Err(e) => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn refuses_denylisted_directories() {
+        with_home("denylist", |_| {
+            for dir in ["/", "/etc", "/usr", "/usr/bin", "/var/log"] {
+                let reason = reason(Path::new(dir), &[]).unwrap();
+                assert!(reason.ends_with("is a system directory"), "{}", reason);
+            }
+        });
+    }
+
+    #[test]
+    fn refuses_links_to_denylisted_directories() {
+        with_home("link", |root| {
+            let link = root.join("etc-link");
+            symlink("/etc", &link).unwrap();
+            assert_eq!(reason(&link, &[]).unwrap(), "/etc is a system directory");
+            let dotted = root.join("home/user/../../../../../../../../etc");
+            assert_eq!(reason(&dotted, &[]).unwrap(), "/etc is a system directory");
+        });
+    }
+
+    #[test]
+    fn refuses_the_home_directory_and_its_parents() {
+        with_home("home", |root| {
+            assert_eq!(
+                reason(&root.join("home/user"), &[]).unwrap(),
+                "it is the home directory"
+            );
+            let parent = root.join("home");
+            assert_eq!(
+                reason(&parent, &[]).unwrap(),
+                format!(
+                    "it contains the home directory {}",
+                    root.join("home/user").display()
+                )
+            );
+            fs::create_dir(root.join("home/user/Downloads")).unwrap();
+            assert_eq!(reason(&root.join("home/user/Downloads"), &[]), None);
+        });
+    }
+
+    #[test]
+    fn allowed_roots_limit_where_cleaning_happens() {
+        with_home("allowed", |root| {
+            let downloads = root.join("downloads/old");
+            fs::create_dir_all(&downloads).unwrap();
+            fs::create_dir(root.join("other")).unwrap();
+            assert_eq!(reason(&downloads, &[root.join("downloads")]), None);
+            assert_eq!(
+                reason(&downloads, &[root.join("missing"), root.join("downloads")]),
+                None
+            );
+            assert_eq!(
+                reason(&downloads, &[root.join("other")]).unwrap(),
+                "it is not under any of the allowed_roots in config"
+            );
+            assert_eq!(
+                reason(&downloads, &[root.join("missing")]).unwrap(),
+                "it is not under any of the allowed_roots in config"
+            );
+        });
+    }
+}
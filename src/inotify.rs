@@ -0,0 +1,156 @@
+// Directory change notifications from inotify(7), for watch mode.
+use std::ffi::{CString, OsString};
+use std::io;
+use std::mem;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::Path;
+use std::time::Duration;
+
+pub use libc::{
+    IN_ACCESS, IN_ATTRIB, IN_CLOSE_WRITE, IN_CREATE, IN_DELETE, IN_IGNORED, IN_ISDIR,
+    IN_MOVED_FROM, IN_MOVED_TO, IN_Q_OVERFLOW,
+};
+
+// Room for many events, each a header plus a name of up to NAME_MAX bytes.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub struct Event {
+    pub wd: i32,
+    pub mask: u32,
+    // The entry inside the watched directory the event is about.
+    pub name: Option<OsString>,
+}
+
+#[derive(Debug)]
+pub struct Inotify {
+    fd: OwnedFd,
+}
+
+impl Inotify {
+    pub fn new() -> io::Result<Inotify> {
+        // SAFETY: inotify_init1 has no preconditions, and a non-negative
+        // result is a new descriptor that nothing else owns.
+        unsafe {
+            let fd = libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Inotify {
+                fd: OwnedFd::from_raw_fd(fd),
+            })
+        }
+    }
+
+    // Watches a directory, returning its watch descriptor. Watching the same
+    // directory again returns the same descriptor.
+    pub fn add_watch(&self, path: &Path, mask: u32) -> io::Result<i32> {
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // SAFETY: the descriptor is open and c_path is NUL terminated.
+        let wd = unsafe {
+            libc::inotify_add_watch(
+                self.fd.as_raw_fd(),
+                c_path.as_ptr(),
+                mask | libc::IN_ONLYDIR,
+            )
+        };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(wd)
+    }
+
+    // Every event queued so far, or none when the queue is empty.
+    pub fn read_events(&self) -> io::Result<Vec<Event>> {
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        let mut events = Vec::new();
+        loop {
+            // SAFETY: the buffer is writable for its whole length.
+            let read = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len(),
+                )
+            };
+            if read < 0 {
+                let error = io::Error::last_os_error();
+                return match error.kind() {
+                    io::ErrorKind::WouldBlock => Ok(events),
+                    io::ErrorKind::Interrupted => continue,
+                    _ => Err(error),
+                };
+            }
+            if read == 0 {
+                return Ok(events);
+            }
+            parse_events(&buffer[..read as usize], &mut events);
+        }
+    }
+}
+
+impl AsFd for Inotify {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+fn parse_events(mut buffer: &[u8], events: &mut Vec<Event>) {
+    let header_size = mem::size_of::<libc::inotify_event>();
+    while buffer.len() >= header_size {
+        // SAFETY: the kernel only returns whole events, and the header is
+        // read unaligned since the buffer is a plain byte array.
+        let header: libc::inotify_event =
+            unsafe { std::ptr::read_unaligned(buffer.as_ptr().cast()) };
+        let end = (header_size + header.len as usize).min(buffer.len());
+        let name: Vec<u8> = buffer[header_size..end]
+            .iter()
+            .copied()
+            .take_while(|&byte| byte != 0)
+            .collect();
+        events.push(Event {
+            wd: header.wd,
+            mask: header.mask,
+            name: (!name.is_empty()).then(|| OsString::from_vec(name)),
+        });
+        buffer = &buffer[end..];
+    }
+}
+
+// Waits until one of `fds` can be read or `timeout` passes, and says which
+// are readable. A signal ends the wait early with nothing readable.
+pub fn poll_readable(fds: &[BorrowedFd], timeout: Option<Duration>) -> io::Result<Vec<bool>> {
+    let mut poll_fds: Vec<libc::pollfd> = fds
+        .iter()
+        .map(|fd| libc::pollfd {
+            fd: fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect();
+    let timeout_ms = match timeout {
+        // Rounded up so a wait never ends just before its deadline.
+        Some(timeout) => timeout.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32,
+        None => -1,
+    };
+    // SAFETY: poll_fds is a valid array of poll_fds.len() entries.
+    let ready = unsafe {
+        libc::poll(
+            poll_fds.as_mut_ptr(),
+            poll_fds.len() as libc::nfds_t,
+            timeout_ms,
+        )
+    };
+    if ready < 0 {
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    Ok(poll_fds
+        .iter()
+        .map(|poll_fd| poll_fd.revents & libc::POLLIN != 0)
+        .collect())
+}
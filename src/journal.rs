@@ -0,0 +1,347 @@
+// Records what each run did to every file so it can be undone later with
+// `clndir restore <run-id>` or `clndir undo`.
+use crate::{archive, xdg};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const RUN_ID_FORMAT: &str = "%Y%m%d-%H%M%S";
+const JOURNAL_EXTENSION: &str = "json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalAction {
+    Deleted,
+    Trashed,
+    // Replaced by a hard or symbolic link to an identical copy.
+    Linked,
+}
+
+// Where a file was archived before it was removed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedAs {
+    pub archive: PathBuf,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    // Seconds since the Unix epoch.
+    pub modified: u64,
+    pub action: JournalAction,
+    // Where the file sits in the trash, for trashed files.
+    pub trashed_to: Option<PathBuf>,
+    pub archived_as: Option<ArchivedAs>,
+    // The copy a linked file now points to.
+    #[serde(default)]
+    pub linked_to: Option<PathBuf>,
+    #[serde(default)]
+    pub restored: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Journal {
+    pub run_id: String,
+    pub entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    pub fn new() -> Journal {
+        Journal {
+            run_id: String::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        path: &Path,
+        size: u64,
+        modified: SystemTime,
+        action: JournalAction,
+        trashed_to: Option<PathBuf>,
+        archived_as: Option<ArchivedAs>,
+    ) {
+        self.entries.push(JournalEntry {
+            path: absolute(path),
+            size,
+            modified: modified
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            action,
+            trashed_to,
+            archived_as,
+            linked_to: None,
+            restored: false,
+        });
+    }
+
+    // Records a duplicate replaced by a link to `linked_to`, the copy kept.
+    pub fn record_link(&mut self, path: &Path, size: u64, modified: SystemTime, linked_to: &Path) {
+        self.record(path, size, modified, JournalAction::Linked, None, None);
+        if let Some(entry) = self.entries.last_mut() {
+            entry.linked_to = Some(absolute(linked_to));
+        }
+    }
+
+    // Writes the journal under a new run id and returns that id. Nothing is
+    // written for a run that did not touch any file.
+    pub fn save(&mut self) -> io::Result<Option<String>> {
+        if self.entries.is_empty() {
+            return Ok(None);
+        }
+        let dir = journal_dir()?;
+        fs::create_dir_all(&dir)?;
+
+        let timestamp = Local::now().format(RUN_ID_FORMAT).to_string();
+        for attempt in 0.. {
+            let run_id = if attempt == 0 {
+                timestamp.clone()
+            } else {
+                format!("{}-{}", timestamp, attempt)
+            };
+            let path = journal_path(&dir, &run_id);
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    self.run_id = run_id;
+                    file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+                    return Ok(Some(self.run_id.clone()));
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        unreachable!()
+    }
+
+    pub fn load(run_id: &str) -> io::Result<Journal> {
+        let path = journal_path(&journal_dir()?, run_id);
+        let contents = fs::read_to_string(&path).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => io::Error::new(
+                ErrorKind::NotFound,
+                format!("no journal for run {}", run_id),
+            ),
+            _ => e,
+        })?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    fn overwrite(&self) -> io::Result<()> {
+        let path = journal_path(&journal_dir()?, &self.run_id);
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+// The id of the most recent run, which run ids sort by.
+pub fn latest_run_id() -> io::Result<Option<String>> {
+    let dir = journal_dir()?;
+    if !dir.is_dir() {
+        return Ok(None);
+    }
+    let mut run_ids = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == JOURNAL_EXTENSION) {
+            if let Some(stem) = path.file_stem() {
+                run_ids.push(stem.to_string_lossy().to_string());
+            }
+        }
+    }
+    run_ids.sort_by(|a, b| run_id_order(a).cmp(&run_id_order(b)));
+    Ok(run_ids.pop())
+}
+
+// Splits off the counter added to run ids that share a second, so that
+// 20240101-120000-10 sorts after 20240101-120000-2.
+fn run_id_order(run_id: &str) -> (&str, u32) {
+    match run_id.rsplit_once('-') {
+        Some((base, counter)) if base.contains('-') => (base, counter.parse().unwrap_or(0)),
+        _ => (run_id, 0),
+    }
+}
+
+// Outcome of restoring a single journal entry.
+pub enum RestoreOutcome {
+    Restored,
+    AlreadyRestored,
+    Failed(String),
+}
+
+// Puts back every file of the run that can be restored. Files that were
+// deleted without an archive are reported as failures.
+pub fn restore_run(run_id: &str) -> io::Result<Vec<(PathBuf, RestoreOutcome)>> {
+    let mut journal = Journal::load(run_id)?;
+    let mut outcomes = Vec::new();
+    for entry in journal.entries.iter_mut() {
+        let outcome = if entry.restored {
+            RestoreOutcome::AlreadyRestored
+        } else {
+            match restore_entry(entry) {
+                Ok(()) => {
+                    entry.restored = true;
+                    RestoreOutcome::Restored
+                }
+                Err(e) => RestoreOutcome::Failed(e.to_string()),
+            }
+        };
+        outcomes.push((entry.path.clone(), outcome));
+    }
+    journal.overwrite()?;
+    Ok(outcomes)
+}
+
+// Whatever now exists at the path is left alone, however old it is.
+fn restore_entry(entry: &JournalEntry) -> io::Result<()> {
+    if entry.action == JournalAction::Linked {
+        return restore_link(entry);
+    }
+    if fs::symlink_metadata(&entry.path).is_ok() {
+        return Err(io::Error::new(
+            ErrorKind::AlreadyExists,
+            "another file now exists at this path",
+        ));
+    }
+    if let Some(parent) = entry.path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    if let Some(trashed_to) = &entry.trashed_to {
+        if trashed_to.symlink_metadata().is_ok() {
+            fs::rename(trashed_to, &entry.path)?;
+            remove_trash_info(trashed_to);
+            return Ok(());
+        }
+    }
+    match &entry.archived_as {
+        Some(archived) => archive::extract_file(&archived.archive, &archived.name, &entry.path),
+        None => Err(io::Error::new(
+            ErrorKind::NotFound,
+            if entry.action == JournalAction::Trashed {
+                "the file is no longer in the trash"
+            } else {
+                "the file was deleted without an archive"
+            },
+        )),
+    }
+}
+
+// Gives a linked file its own copy of the kept file again, as long as the
+// path still holds the link clndir made.
+fn restore_link(entry: &JournalEntry) -> io::Result<()> {
+    let replaced = || {
+        io::Error::new(
+            ErrorKind::AlreadyExists,
+            "another file now exists at this path",
+        )
+    };
+    let kept = entry
+        .linked_to
+        .as_deref()
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "the journal has no kept copy"))?;
+    let link = fs::symlink_metadata(&entry.path).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => io::Error::new(ErrorKind::NotFound, "the link was removed"),
+        _ => e,
+    })?;
+    let kept_metadata = fs::metadata(kept).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => io::Error::new(ErrorKind::NotFound, "the kept copy was removed"),
+        _ => e,
+    })?;
+    let still_linked = if link.file_type().is_symlink() {
+        fs::canonicalize(&entry.path).is_ok_and(|target| target == kept)
+    } else {
+        (link.dev(), link.ino()) == (kept_metadata.dev(), kept_metadata.ino())
+    };
+    if !still_linked {
+        return Err(replaced());
+    }
+    if kept_metadata.len() != entry.size {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "the kept copy has changed",
+        ));
+    }
+    // Copied under a temporary name and renamed over the link, so the file
+    // is never missing.
+    let file_name = entry.path.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = entry
+        .path
+        .with_file_name(format!(".{}.clndir-restore", file_name));
+    let copy = || {
+        fs::copy(kept, &temporary)?;
+        File::options()
+            .write(true)
+            .open(&temporary)?
+            .set_modified(UNIX_EPOCH + Duration::from_secs(entry.modified))?;
+        fs::rename(&temporary, &entry.path)
+    };
+    copy().inspect_err(|_| {
+        let _ = fs::remove_file(&temporary);
+    })
+}
+
+// Removes the .trashinfo that belongs to a file taken back out of the trash.
+fn remove_trash_info(trashed_to: &Path) {
+    if let (Some(files_dir), Some(name)) = (trashed_to.parent(), trashed_to.file_name()) {
+        if let Some(trash_root) = files_dir.parent() {
+            let info = trash_root
+                .join("info")
+                .join(format!("{}.trashinfo", name.to_string_lossy()));
+            let _ = fs::remove_file(info);
+        }
+    }
+}
+
+// `path` with its parent directory canonicalized. The file itself is not
+// resolved, as it may be a link.
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path.parent().unwrap_or(path))
+        .map(|parent| parent.join(path.file_name().unwrap_or_default()))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn journal_path(dir: &Path, run_id: &str) -> PathBuf {
+    dir.join(format!("{}.{}", run_id, JOURNAL_EXTENSION))
+}
+
+fn journal_dir() -> io::Result<PathBuf> {
+    Ok(xdg::state_home()?.join("clndir").join("journal"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_id_order_splits_off_the_counter() {
+        assert_eq!(run_id_order("20240101-120000"), ("20240101-120000", 0));
+        assert_eq!(run_id_order("20240101-120000-10"), ("20240101-120000", 10));
+        assert_eq!(run_id_order("latest"), ("latest", 0));
+    }
+
+    #[test]
+    fn run_ids_of_one_second_sort_by_counter() {
+        let mut run_ids = vec![
+            "20240101-120000-10",
+            "20240101-120001",
+            "20240101-120000",
+            "20240101-120000-2",
+        ];
+        run_ids.sort_by(|a, b| run_id_order(a).cmp(&run_id_order(b)));
+        assert_eq!(
+            run_ids,
+            [
+                "20240101-120000",
+                "20240101-120000-2",
+                "20240101-120000-10",
+                "20240101-120001",
+            ]
+        );
+    }
+}
@@ -0,0 +1,29 @@
+// The selection and removal logic behind the clndir command. A Scanner
+// records the files of a directory, a Planner picks the ones a
+// CleanupPolicy removes into a Plan, and an Executor applies the plan and
+// reports what it did.
+pub mod age;
+pub mod archive;
+pub mod clndirignore;
+pub mod dedupe;
+pub mod error;
+pub mod executor;
+pub mod fsstat;
+pub mod guard;
+pub mod inotify;
+pub mod journal;
+pub mod openfiles;
+pub mod pattern;
+pub mod planner;
+pub mod policy;
+pub mod retention;
+pub mod scanner;
+pub mod size;
+pub mod trash;
+pub mod xdg;
+
+pub use error::{ClndirError, Warning};
+pub use executor::{Action, ArchiveTarget, Executor, Report};
+pub use planner::{Candidate, Plan, Planner};
+pub use policy::{CleanupPolicy, TimeField};
+pub use scanner::{FileRecord, Scanner};
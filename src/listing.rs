@@ -0,0 +1,351 @@
+// The list of candidates shown for confirmation and by dry runs: sorted,
+// grouped, in aligned columns fitted to the terminal, and sent through
+// $PAGER when it is longer than the screen.
+use crate::age_in_days;
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use clndir::planner::Plan;
+use clndir::size::format_size;
+use clndir::{FileRecord, TimeField};
+use colored::*;
+use ratatui::crossterm::terminal;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+const NO_EXTENSION: &str = "(none)";
+const DEFAULT_PAGER: &str = "less";
+// Lets less exit on short input and keep the colors, as git does.
+const DEFAULT_LESS: &str = "FRX";
+// Exit status of sh when the pager command is not found.
+const COMMAND_NOT_FOUND: i32 = 127;
+const SIZE_WIDTH: usize = 8;
+const AGE_WIDTH: usize = 6;
+// Fits "broken symlink".
+const TYPE_WIDTH: usize = 14;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum SortKey {
+    // Oldest first.
+    Age,
+    // Largest first.
+    Size,
+    Name,
+    Ext,
+}
+
+impl SortKey {
+    pub fn next(self) -> SortKey {
+        match self {
+            SortKey::Age => SortKey::Size,
+            SortKey::Size => SortKey::Name,
+            SortKey::Name => SortKey::Ext,
+            SortKey::Ext => SortKey::Age,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortKey::Age => "age",
+            SortKey::Size => "size",
+            SortKey::Name => "name",
+            SortKey::Ext => "extension",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum GroupBy {
+    Ext,
+    // The month of the file's timestamp.
+    Month,
+    // The directory the file is in.
+    Origin,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Listing {
+    pub sort: SortKey,
+    pub group_by: Option<GroupBy>,
+}
+
+struct Entry<'a> {
+    file: &'a FileRecord,
+    // The directory joined with the relative path when several directories
+    // are cleaned, else the relative path.
+    path: String,
+    origin: String,
+    time: SystemTime,
+    role: Role<'a>,
+    // In dedupe mode, the location of the copy kept of the entry's set.
+    set: Option<PathBuf>,
+}
+
+impl<'a> Entry<'a> {
+    // `several` is whether several directories are listed.
+    fn new(
+        dir: &str,
+        several: bool,
+        file: &'a FileRecord,
+        role: Role<'a>,
+        time_field: TimeField,
+    ) -> Entry<'a> {
+        let location = Path::new(dir).join(&file.name);
+        Entry {
+            file,
+            path: if several {
+                location.to_string_lossy().to_string()
+            } else {
+                file.name.clone()
+            },
+            origin: location
+                .parent()
+                .map(|parent| parent.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            time: file.time(time_field),
+            role,
+            set: None,
+        }
+    }
+}
+
+// Why a file is listed, shown after its name.
+enum Role<'a> {
+    Candidate,
+    Duplicate(&'a str),
+    // Listed but left in place, for the candidate's reason.
+    HeldBack(&'a str),
+    // The copy dedupe keeps of a set.
+    Kept,
+}
+
+// The extension of a file name as shown in listings and summaries.
+pub fn extension_label(name: &str) -> String {
+    Path::new(name)
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy().to_lowercase()))
+        .unwrap_or_else(|| NO_EXTENSION.to_string())
+}
+
+// Without --group-by, files are listed under a heading per directory when
+// there is more than one. In dedupe mode each set of duplicates stays
+// together, in the group of its kept copy, which is listed first.
+pub fn print_files(plan: &Plan, time_field: TimeField, listing: Listing) {
+    let several = plan.dirs.len() > 1;
+    let mut groups: BTreeMap<String, Vec<Entry>> = BTreeMap::new();
+    for dir in &plan.dirs {
+        let entry = |file, role| Entry::new(&dir.dir, several, file, role, time_field);
+        let group = |entry: &Entry| match listing.group_by {
+            Some(GroupBy::Ext) => extension_label(&entry.file.name),
+            Some(GroupBy::Month) => DateTime::<Local>::from(entry.time)
+                .format("%Y-%m")
+                .to_string(),
+            Some(GroupBy::Origin) => entry.origin.clone(),
+            None if several => dir.dir.clone(),
+            None => String::new(),
+        };
+
+        // The group of each kept copy, by name.
+        let mut kept_groups: HashMap<&str, String> = HashMap::new();
+        for file in &dir.kept {
+            let mut kept = entry(file, Role::Kept);
+            kept.set = Some(Path::new(&dir.dir).join(&file.name));
+            let name = group(&kept);
+            kept_groups.insert(&file.name, name.clone());
+            groups.entry(name).or_default().push(kept);
+        }
+        let held_back = dir
+            .held_back
+            .iter()
+            .map(|candidate| (candidate, Role::HeldBack(&candidate.reason)));
+        let candidates = dir.candidates.iter().map(|candidate| {
+            let role = match &candidate.duplicate_of {
+                Some(kept) => Role::Duplicate(kept),
+                None => Role::Candidate,
+            };
+            (candidate, role)
+        });
+        for (candidate, role) in candidates.chain(held_back) {
+            let mut entry = entry(&candidate.file, role);
+            let name = match &candidate.duplicate_of {
+                Some(kept) => {
+                    entry.set = Some(Path::new(&dir.dir).join(kept));
+                    kept_groups.get(kept.as_str()).cloned()
+                }
+                None => None,
+            };
+            let name = name.unwrap_or_else(|| group(&entry));
+            groups.entry(name).or_default().push(entry);
+        }
+    }
+
+    // The format string is shorter than the timestamps it renders.
+    let timestamp_width = DateTime::<Local>::from(SystemTime::UNIX_EPOCH)
+        .format(TIME_FORMAT)
+        .to_string()
+        .len();
+    let time_width = timestamp_width.max(time_field.label().len());
+    let name_width = terminal_size().map(|(columns, _)| {
+        usize::from(columns).saturating_sub(SIZE_WIDTH + AGE_WIDTH + time_width + TYPE_WIDTH + 4)
+    });
+    let mut lines = vec![format!(
+        "{:>SIZE_WIDTH$} {:>AGE_WIDTH$} {:<time_width$} {:<TYPE_WIDTH$} {}",
+        "Size",
+        "Age",
+        time_field.label(),
+        "Type",
+        "Name"
+    )
+    .bold()
+    .to_string()];
+    for (group, entries) in groups {
+        if !group.is_empty() {
+            // Kept copies are only listed to show what their duplicates
+            // are of.
+            let listed: Vec<&Entry> = entries
+                .iter()
+                .filter(|entry| !matches!(entry.role, Role::Kept))
+                .collect();
+            let bytes: u64 = listed.iter().map(|entry| entry.file.size).sum();
+            lines.push(String::new());
+            lines.push(format!(
+                "{} ({} File(s), {})",
+                group.cyan(),
+                listed.len(),
+                format_size(bytes)
+            ));
+        }
+        for entry in sort(entries, listing.sort) {
+            let (note, note_width) = match entry.role {
+                Role::Candidate => (String::new(), 0),
+                Role::Duplicate(kept) => {
+                    let text = format!(" duplicate of {}", kept);
+                    let width = text.chars().count();
+                    (text.cyan().to_string(), width)
+                }
+                Role::HeldBack(reason) => {
+                    let text = format!(" held back, {}", reason);
+                    let width = text.chars().count();
+                    (text.red().to_string(), width)
+                }
+                Role::Kept => {
+                    let text = " kept".to_string();
+                    let width = text.chars().count();
+                    (text.green().to_string(), width)
+                }
+            };
+            let name = match name_width {
+                Some(width) => fit(&entry.path, width.saturating_sub(note_width)),
+                None => entry.path.clone(),
+            };
+            let time = DateTime::<Local>::from(entry.time).format(TIME_FORMAT);
+            lines.push(format!(
+                "{:>SIZE_WIDTH$} {:>AGE_WIDTH$} {} {:<TYPE_WIDTH$} {}{}",
+                format_size(entry.file.size),
+                format!("{}d", age_in_days(entry.time)),
+                format!("{:<time_width$}", time.to_string()).green(),
+                entry.file.kind.label(),
+                name.yellow(),
+                note,
+            ));
+        }
+    }
+    print_lines(&lines);
+}
+
+// Sorts the entries by `key`. Sets of duplicates are sorted within and kept
+// together, their kept copy first, and ordered among the other entries by
+// that copy.
+fn sort(entries: Vec<Entry>, key: SortKey) -> Vec<Entry> {
+    let mut sets: Vec<Vec<Entry>> = Vec::new();
+    let mut set_indexes: HashMap<PathBuf, usize> = HashMap::new();
+    for entry in entries {
+        match entry.set.clone() {
+            Some(set) => {
+                let index = *set_indexes.entry(set).or_insert_with(|| {
+                    sets.push(Vec::new());
+                    sets.len() - 1
+                });
+                sets[index].push(entry);
+            }
+            None => sets.push(vec![entry]),
+        }
+    }
+    for set in &mut sets {
+        set.sort_by(|a, b| compare(a, b, key));
+        set.sort_by_key(|entry| !matches!(entry.role, Role::Kept));
+    }
+    sets.sort_by(|a, b| compare(&a[0], &b[0], key));
+    sets.into_iter().flatten().collect()
+}
+
+fn compare(a: &Entry, b: &Entry, key: SortKey) -> Ordering {
+    match key {
+        SortKey::Age => a.time.cmp(&b.time),
+        SortKey::Size => b.file.size.cmp(&a.file.size),
+        SortKey::Name => a.path.cmp(&b.path),
+        SortKey::Ext => extension_label(&a.path)
+            .cmp(&extension_label(&b.path))
+            .then(a.path.cmp(&b.path)),
+    }
+}
+
+// Shortens a name to `width` characters, keeping its end where the file
+// name and extension are.
+fn fit(name: &str, width: usize) -> String {
+    let length = name.chars().count();
+    if length <= width || width == 0 {
+        return name.to_string();
+    }
+    let tail: String = name.chars().skip(length - width + 1).collect();
+    format!("…{}", tail)
+}
+
+// Columns and rows of the terminal, None when output is not a terminal.
+fn terminal_size() -> Option<(u16, u16)> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    terminal::size().ok()
+}
+
+// Pages the lines when they do not fit on the screen, leaving room for the
+// prompt that follows. They are printed as they are if the pager fails.
+fn print_lines(lines: &[String]) {
+    let too_long = terminal_size().is_some_and(|(_, rows)| lines.len() + 2 > usize::from(rows));
+    if too_long && page(lines).unwrap_or(false) {
+        return;
+    }
+    for line in lines {
+        println!("{}", line);
+    }
+}
+
+// Returns false when there is no pager to run.
+fn page(lines: &[String]) -> io::Result<bool> {
+    let pager = env::var("PAGER").unwrap_or_else(|_| DEFAULT_PAGER.to_string());
+    if pager.trim().is_empty() || pager.trim() == "cat" {
+        return Ok(false);
+    }
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(&pager).stdin(Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", DEFAULT_LESS);
+    }
+    let mut child = command.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        for line in lines {
+            // The pager may be quit before reading everything.
+            if writeln!(stdin, "{}", line).is_err() {
+                break;
+            }
+        }
+    }
+    let status = child.wait()?;
+    Ok(status.code() != Some(COMMAND_NOT_FOUND))
+}
@@ -1,193 +1,686 @@
+mod config;
+mod listing;
+mod plan;
+mod schedule;
+mod summary;
+mod tui;
+mod watch;
+
 use chrono::prelude::*;
-use chrono::Utc;
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use clndir::age::{self, Age, TimeWindow};
+use clndir::archive::ArchiveFormat;
+use clndir::dedupe::KeepPolicy;
+use clndir::error::{
+    EXIT_CANCELLED, EXIT_ERROR, EXIT_NOTHING_TO_DO, EXIT_PARTIAL_FAILURE, EXIT_SUCCESS,
+};
+use clndir::guard;
+use clndir::journal::{self, RestoreOutcome};
+use clndir::pattern::Pattern;
+use clndir::planner::Plan;
+use clndir::policy::{
+    BrokenSymlinks, DepthRange, EvictBy, FileTypes, FreeSpace, Hidden, Quota, SymlinkPolicy,
+};
+use clndir::retention::RetentionPolicy;
+use clndir::size::{self, SpaceAmount};
+use clndir::{Action, ArchiveTarget, CleanupPolicy, ClndirError, Executor, Planner, TimeField};
 use colored::*;
+use config::{Config, ConfirmMode, Profile};
+use listing::{GroupBy, Listing, SortKey};
+use plan::{PlanFormat, PlannedFile};
+use schedule::ScheduleCommand;
 use std::io::Write;
-use std::{env, fs, io, path::Path, process, time::SystemTime};
+use std::{env, io, path::PathBuf, process, time::SystemTime};
+use summary::Summary;
 
 // Key for Env variable used to store the path to the Downloads folder.
 const DOWNLOADS: &str = "Downloads";
 const SECS_IN_A_DAY: u64 = 60 * 60 * 24;
 const DELETE_COMMAND: &str = "DEL";
-const DATE_FORMAT: &str = "%Y-%m-%d";
-const DEFAULT_NUMBER_OF_DAYS: u64 = 180;
+const DEFAULT_NUMBER_OF_DAYS: u32 = 180;
 
 #[derive(Parser)]
 #[command(name = "clndir")]
 #[command(version = "1.0")]
+#[command(args_conflicts_with_subcommands = true)]
 #[command(
-    about = "Cleans old files from a directory. It defaults to the value of the ENV var 'downloads'.\nProgram will return an error if no directory is specified and the ENV var is missing.\nProgram will ask user to confirm list of files unless --nowarn is specified.\nOnly files older than --age days will be deleted.\nFiles matching the pattern specified by --SKIP will not be deleted. This parameter can be repeated."
+    about = "Cleans old files from a directory. Several directories can be given with repeated --dir or as arguments, and are confirmed together. It defaults to the value of the ENV var 'downloads'.\nProgram will return an error if no directory is specified and the ENV var is missing.\nProgram will ask user to confirm list of files unless --nowarn is specified.\nOnly files older than --age will be deleted, given in days or as 36h, 2w, 6mo or 1y. --before and --after take dates such as 2024-01-01, and --newer-than an age, to clean a range of dates instead. Age is measured from the time picked by --time-field, the last modification by default.\nWith --max-total-size, the oldest files are removed until the directory fits in that size. --age then defaults to 0.\nWith --when-free-below or --target-free, nothing is done while the filesystem has enough free space, otherwise the oldest files are removed until --target-free is reached.\nWith --keep-last, --keep-daily, --keep-weekly, --keep-monthly or --keep-yearly, files whose names only differ in their digits form a series, and the files of a series that no rule keeps are removed.\nFiles matching the pattern specified by --SKIP will not be deleted. This parameter can be repeated.\nWith --only, just the files matching one of its patterns are considered.\nPatterns are substrings, globs such as *.pdf, or regular expressions prefixed with re:. Matching ignores case unless --case-sensitive is given.\nFiles matched by a .clndirignore file (gitignore syntax) in the directory or one of its subdirectories are never deleted.\nSymbolic links are left alone unless --symlinks delete-link removes them by their own age, or --symlinks follow judges them by the file they point to; only the link is ever removed. --broken-symlinks delete removes links whose target is missing. --hidden exclude leaves files and directories starting with a dot alone. FIFOs, sockets and device nodes are never removed.\nFiles that a running process has open or locked are held back: they are listed, marked as such, but not removed.\nSystem directories such as / or /etc, the home directory and its parents, and filesystem roots are refused, as are directories outside the allowed_roots of the config file when it has some. --i-know-what-im-doing overrides this.\nWith --recursive, files in subdirectories are cleaned too, limited by --min-depth and --max-depth.\nWith --trash, files are moved to the freedesktop.org trash instead of being deleted.\nWith --archive, files are packed into a dated tarball in the given directory before they are removed.\nEvery run is journaled so it can be reverted with the restore and undo commands.\nWith --interactive, the files are picked in a terminal UI that can sort, filter and preview them, instead of confirming the whole list.\nWith --dry-run, the files that would be removed are listed, as text or as --format json, csv or ndjson, and nothing is changed.\nAfter a run, a summary breaks down the files removed and bytes freed by extension and age, with the files kept by skip rules and the errors. --summary-only shows it instead of the list of files.\nThe list of files can be ordered with --sort age, size, name or ext and split with --group-by ext, month or origin. It is shown through $PAGER when longer than the screen.\nExits with 0 when files were removed, 1 on errors, 2 when there was nothing to do, 3 when some files could not be removed and 4 when cancelled.\nThe dedupe command removes, or replaces with links, files whose contents duplicate another file.\nThe schedule command installs, lists and removes systemd user timers that run a profile, or prints a crontab line with install --cron.\nThe watch command keeps running and removes each file the moment it gets older than --age, reloading its --profile on SIGHUP.\nThe run command applies a named profile from $XDG_CONFIG_HOME/clndir/config.toml, or every profile with --all. Options given on the command line override the profile."
 )]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    clean: CleanArgs,
+}
+
+#[derive(Args)]
+struct CleanArgs {
     #[arg(short, long)]
-    dir: Option<String>,
-    #[arg(short, long, default_value_t = DEFAULT_NUMBER_OF_DAYS)]
-    age: u64,
+    dir: Vec<String>,
+    // Positional directories, cleaned together with any given by --dir.
+    #[arg(value_name = "DIR")]
+    dirs: Vec<String>,
+    // Defaults to DEFAULT_NUMBER_OF_DAYS when neither set here nor in a
+    // profile, unless another option picks which files go.
+    #[arg(short, long, value_parser = age::parse_age)]
+    age: Option<Age>,
+    #[arg(long, value_name = "DATE", value_parser = age::parse_date)]
+    before: Option<DateTime<Local>>,
+    #[arg(long, value_name = "DATE", value_parser = age::parse_date)]
+    after: Option<DateTime<Local>>,
+    #[arg(long, value_name = "AGE", value_parser = age::parse_age)]
+    newer_than: Option<Age>,
+    #[arg(long, value_enum)]
+    time_field: Option<TimeField>,
+    #[arg(long, value_name = "SIZE", value_parser = size::parse_size)]
+    max_total_size: Option<u64>,
+    // Only clean when the filesystem has less than this free, as a size or %.
+    #[arg(long, value_name = "SIZE|PERCENT", value_parser = size::parse_space_amount)]
+    when_free_below: Option<SpaceAmount>,
+    // How much free space to make, defaulting to --when-free-below.
+    #[arg(long, value_name = "SIZE|PERCENT", value_parser = size::parse_space_amount)]
+    target_free: Option<SpaceAmount>,
+    #[arg(long, value_enum, default_value_t = EvictBy::Age)]
+    evict_by: EvictBy,
+    #[arg(long, value_name = "N")]
+    keep_last: Option<usize>,
+    #[arg(long, value_name = "N")]
+    keep_daily: Option<usize>,
+    #[arg(long, value_name = "N")]
+    keep_weekly: Option<usize>,
+    #[arg(long, value_name = "N")]
+    keep_monthly: Option<usize>,
+    #[arg(long, value_name = "N")]
+    keep_yearly: Option<usize>,
     #[arg(short, long)]
     nowarn: bool,
     #[arg(short, long)]
     skip: Vec<String>,
+    #[arg(short, long)]
+    only: Vec<String>,
+    #[arg(long)]
+    case_sensitive: bool,
+    #[arg(short, long)]
+    recursive: bool,
+    // Depth 1 is the files directly inside the directory being cleaned.
+    #[arg(long)]
+    max_depth: Option<usize>,
+    #[arg(long, default_value_t = 1)]
+    min_depth: usize,
+    // Defaults to skip, leaving links alone.
+    #[arg(long, value_enum)]
+    symlinks: Option<SymlinkPolicy>,
+    #[arg(long, value_enum)]
+    broken_symlinks: Option<BrokenSymlinks>,
+    #[arg(long, value_enum)]
+    hidden: Option<Hidden>,
+    #[arg(short, long)]
+    trash: bool,
+    #[arg(long, value_name = "DIR")]
+    archive: Option<PathBuf>,
+    #[arg(long, value_enum, requires = "archive", default_value_t = ArchiveFormat::Gz)]
+    archive_format: ArchiveFormat,
+    #[arg(long)]
+    dry_run: bool,
+    #[arg(long, value_enum, requires = "dry_run", default_value_t = PlanFormat::Text)]
+    format: PlanFormat,
+    #[arg(short, long, conflicts_with_all = ["nowarn", "dry_run"])]
+    interactive: bool,
+    // Show the summary table without listing every file.
+    #[arg(long, conflicts_with = "interactive")]
+    summary_only: bool,
+    #[arg(long, value_enum, default_value_t = SortKey::Age)]
+    sort: SortKey,
+    #[arg(long, value_enum)]
+    group_by: Option<GroupBy>,
+    // Cleans directories the safety guard would refuse.
+    #[arg(long)]
+    i_know_what_im_doing: bool,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Put back the files removed by the given run
+    Restore { run_id: String },
+    /// Put back the files removed by the most recent run
+    Undo,
+    /// Clean using a profile from the config file
+    Run {
+        #[arg(required_unless_present = "all")]
+        profile: Option<String>,
+        /// Run every profile in the config file
+        #[arg(long, conflicts_with = "profile")]
+        all: bool,
+        #[command(flatten)]
+        clean: Box<CleanArgs>,
+    },
+    /// Remove files whose contents duplicate another file
+    Dedupe {
+        /// Which copy of each set of duplicates to keep
+        #[arg(long, value_enum, default_value_t = KeepPolicy::Oldest)]
+        keep: KeepPolicy,
+        /// Replace duplicates with links to the kept copy instead of removing them
+        #[arg(long, value_enum, conflicts_with = "trash")]
+        link: Option<LinkKind>,
+        #[command(flatten)]
+        clean: Box<CleanArgs>,
+    },
+    /// Run a profile periodically with a systemd user timer or cron
+    Schedule {
+        #[command(subcommand)]
+        command: ScheduleCommand,
+    },
+    /// Keep running and remove each file as soon as it is older than --age
+    Watch {
+        /// Take the settings from this profile, reloaded on SIGHUP
+        #[arg(long)]
+        profile: Option<String>,
+        #[command(flatten)]
+        clean: Box<CleanArgs>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum LinkKind {
+    Hard,
+    Sym,
+}
+
+// Settings of the dedupe command.
+#[derive(Debug, Clone, Copy)]
+struct Dedupe {
+    keep: KeepPolicy,
+    link: Option<LinkKind>,
+}
+
+// Settings for a cleaning run, from the command line merged over a profile.
 #[derive(Debug)]
-struct FileWithModifiedTime {
-    name: String,
-    modified_time: SystemTime,
+struct CleanOptions {
+    policy: CleanupPolicy,
+    nowarn: bool,
+    action: Action,
+    archive: Option<ArchiveTarget>,
+    dry_run: bool,
+    format: PlanFormat,
+    interactive: bool,
+    summary_only: bool,
+    listing: Listing,
+}
+
+// How a command that did not fail ended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    Done,
+    NothingToDo,
 }
 
 fn main() {
-    let cli = Cli::parse();
-
-    let dir = match cli.dir {
-        Some(dir) => dir,
-        _ => match read_env_variable(DOWNLOADS) {
-            Ok(value) => value,
-            Err(err) => {
-                eprintln!("Error: {}", err);
-                process::exit(1);
-            }
-        },
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            // --help and --version are not errors.
+            process::exit(if e.use_stderr() {
+                EXIT_ERROR
+            } else {
+                EXIT_SUCCESS
+            });
+        }
     };
 
-    let exit_code = clean_dir(&dir, cli.age, cli.skip, cli.nowarn);
-    match exit_code {
-        Ok(_) => process::exit(0),
+    let exit_code = match cli.command {
+        Some(Command::Restore { run_id }) => finish(restore(&run_id)),
+        Some(Command::Undo) => finish(undo()),
+        Some(Command::Run {
+            profile,
+            all,
+            clean,
+        }) => run_profiles(profile.as_deref(), all, &clean),
+        Some(Command::Dedupe { keep, link, clean }) => finish(run_clean(
+            &clean,
+            &Profile::default(),
+            Some(Dedupe { keep, link }),
+        )),
+        Some(Command::Schedule { command }) => finish(schedule::schedule(&command)),
+        Some(Command::Watch { profile, clean }) => finish(watch::watch(&clean, profile.as_deref())),
+        None => finish(run_clean(&cli.clean, &Profile::default(), None)),
+    };
+    process::exit(exit_code);
+}
+
+// Reports how a command ended and returns its exit code.
+fn finish(result: Result<Outcome, ClndirError>) -> i32 {
+    match result {
+        Ok(Outcome::Done) => EXIT_SUCCESS,
+        Ok(Outcome::NothingToDo) => EXIT_NOTHING_TO_DO,
+        Err(ClndirError::Cancelled) => {
+            println!("{}", ClndirError::Cancelled);
+            EXIT_CANCELLED
+        }
         Err(e) => {
-            println!("Error : {}\n", e.to_string());
-            process::exit(1);
+            eprintln!("Error: {}", e);
+            e.exit_code()
         }
     }
 }
 
-fn read_env_variable(var_name: &str) -> Result<String, String> {
-    match env::var(var_name) {
-        Ok(value) => Ok(value),
-        Err(_) => Err(format!("Environment variable {} not found", var_name)),
+// With --all, every profile is run and the exit code is that of the worst
+// outcome, so one failing profile is not hidden by the others.
+fn run_profiles(name: Option<&str>, all: bool, args: &CleanArgs) -> i32 {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => return finish(Err(ClndirError::Config(e.to_string()))),
+    };
+    if !all {
+        return finish(
+            config
+                .profile(name.unwrap_or_default())
+                .map_err(|e| ClndirError::Config(e.to_string()))
+                .and_then(|profile| run_clean(args, profile, None)),
+        );
     }
+    if config.profiles.is_empty() {
+        return finish(Err(ClndirError::Config(
+            "No profiles in config".to_string(),
+        )));
+    }
+    let severity = |code: &i32| match *code {
+        EXIT_ERROR => 4,
+        EXIT_PARTIAL_FAILURE => 3,
+        EXIT_CANCELLED => 2,
+        EXIT_SUCCESS => 1,
+        _ => 0,
+    };
+    config
+        .profiles
+        .iter()
+        .map(|(name, profile)| {
+            println!("{} {}", "Profile".cyan(), name.cyan());
+            finish(run_clean(args, profile, None))
+        })
+        .max_by_key(severity)
+        .unwrap_or(EXIT_NOTHING_TO_DO)
 }
-fn clean_dir(
-    dir: &str,
-    age: u64,
-    skip: Vec<String>,
-    nowarn: bool,
-) -> Result<u8, Box<dyn std::error::Error>> {
-    match list_files_with_modified_time(dir) {
-        Ok(files) => {
-            match_and_delete(dir, files, age, skip, nowarn);
-            Ok(0)
+
+fn run_clean(
+    args: &CleanArgs,
+    profile: &Profile,
+    dedupe: Option<Dedupe>,
+) -> Result<Outcome, ClndirError> {
+    let dirs = target_dirs(args, profile)?;
+    let options = clean_options(args, profile, dedupe)?;
+    clean_dirs(&dirs, &options)
+}
+
+// The directories given on the command line, else the profile's, else the
+// one in the Downloads environment variable. Each must pass the safety guard
+// unless --i-know-what-im-doing is given.
+fn target_dirs(args: &CleanArgs, profile: &Profile) -> Result<Vec<String>, ClndirError> {
+    let mut dirs: Vec<String> = args.dir.iter().chain(&args.dirs).cloned().collect();
+    if dirs.is_empty() {
+        match profile.dir().or_else(|| env::var(DOWNLOADS).ok()) {
+            Some(dir) => dirs.push(dir),
+            None => return Err(ClndirError::NoDirectory(DOWNLOADS.to_string())),
         }
-        Err(e) => {
-            eprintln!("\nDirectory name : {}", dir);
-            Err(Box::new(e))
+    }
+    if !args.i_know_what_im_doing {
+        let config = Config::load().map_err(|e| ClndirError::Config(e.to_string()))?;
+        let allowed_roots = config.allowed_roots();
+        for dir in &dirs {
+            guard::check_dir(dir, &allowed_roots)?;
         }
     }
+    Ok(dirs)
 }
 
-fn match_and_delete(
-    dir: &str,
-    files: Vec<FileWithModifiedTime>,
-    age: u64,
-    skip: Vec<String>,
-    nowarn: bool,
-) {
-    let mut files_to_delete: Vec<FileWithModifiedTime> = Vec::new();
+// Merges the command line over the profile. Skip patterns from both apply,
+// while --only replaces the profile's list.
+fn clean_options(
+    args: &CleanArgs,
+    profile: &Profile,
+    dedupe: Option<Dedupe>,
+) -> Result<CleanOptions, ClndirError> {
+    let recursive = args.recursive || profile.recursive.unwrap_or(false);
+    if !recursive && (args.max_depth.is_some() || args.min_depth != 1) {
+        return Err(ClndirError::InvalidArgument(
+            "--min-depth and --max-depth require --recursive".to_string(),
+        ));
+    }
+    let depth = DepthRange {
+        min: args.min_depth,
+        max: match (recursive, args.max_depth) {
+            (false, _) => 1,
+            (true, Some(max)) => max,
+            (true, None) => usize::MAX,
+        },
+    };
+
+    let action = match (args.trash, dedupe.and_then(|dedupe| dedupe.link)) {
+        (_, Some(LinkKind::Hard)) => Action::Hardlink,
+        (_, Some(LinkKind::Sym)) => Action::Symlink,
+        (true, None) => Action::Trash,
+        (false, None) => profile.action.unwrap_or(Action::Delete),
+    };
+
+    let archive = args.archive.clone().map(|dir| ArchiveTarget {
+        dir,
+        format: args.archive_format,
+    });
+
+    let skip: Vec<String> = profile.skip.iter().chain(&args.skip).cloned().collect();
+    let only = if args.only.is_empty() {
+        &profile.only
+    } else {
+        &args.only
+    };
+    let skip = Pattern::parse_all(&skip, args.case_sensitive)?;
+    let only = Pattern::parse_all(only, args.case_sensitive)?;
 
-    for file in files {
-        if is_file_ok_to_delete(&file, age, &skip) {
-            files_to_delete.push(file);
+    let quota = args
+        .max_total_size
+        .map(|max_total_size| Quota { max_total_size });
+    let free_space = match (args.when_free_below, args.target_free) {
+        (None, None) => None,
+        (trigger, target) => Some(FreeSpace {
+            trigger: trigger.or(target).unwrap_or(SpaceAmount::Bytes(0)),
+            target: target.or(trigger).unwrap_or(SpaceAmount::Bytes(0)),
+        }),
+    };
+    let keep_rules = [
+        args.keep_last,
+        args.keep_daily,
+        args.keep_weekly,
+        args.keep_monthly,
+        args.keep_yearly,
+    ];
+    let retention = keep_rules
+        .iter()
+        .any(Option::is_some)
+        .then(|| RetentionPolicy {
+            last: args.keep_last.unwrap_or(0),
+            daily: args.keep_daily.unwrap_or(0),
+            weekly: args.keep_weekly.unwrap_or(0),
+            monthly: args.keep_monthly.unwrap_or(0),
+            yearly: args.keep_yearly.unwrap_or(0),
+        });
+    // A quota, free space target, retention policy, dedupe or date range
+    // alone should be able to remove files of any age.
+    let default_age = if quota.is_some()
+        || free_space.is_some()
+        || retention.is_some()
+        || dedupe.is_some()
+        || args.before.is_some()
+        || args.after.is_some()
+        || args.newer_than.is_some()
+    {
+        None
+    } else {
+        Some(Age::days(DEFAULT_NUMBER_OF_DAYS))
+    };
+    let window = TimeWindow {
+        older_than: args.age.or(profile.age).or(default_age),
+        newer_than: args.newer_than,
+        before: args.before,
+        after: args.after,
+    };
+
+    Ok(CleanOptions {
+        policy: CleanupPolicy {
+            window,
+            time_field: args
+                .time_field
+                .or(profile.time_field)
+                .unwrap_or(TimeField::Mtime),
+            quota,
+            free_space,
+            evict_by: args.evict_by,
+            retention,
+            dedupe: dedupe.map(|dedupe| dedupe.keep),
+            skip,
+            only,
+            ignore_files_case_sensitive: args.case_sensitive,
+            depth,
+            file_types: FileTypes {
+                symlinks: args.symlinks.or(profile.symlinks).unwrap_or_default(),
+                broken_symlinks: args
+                    .broken_symlinks
+                    .or(profile.broken_symlinks)
+                    .unwrap_or_default(),
+                hidden: args.hidden.or(profile.hidden).unwrap_or_default(),
+            },
+        },
+        nowarn: args.nowarn || profile.confirm == Some(ConfirmMode::Never),
+        action,
+        archive,
+        dry_run: args.dry_run,
+        format: args.format,
+        interactive: args.interactive,
+        summary_only: args.summary_only,
+        listing: Listing {
+            sort: args.sort,
+            group_by: args.group_by,
+        },
+    })
+}
+
+fn undo() -> Result<Outcome, ClndirError> {
+    match journal::latest_run_id() {
+        Ok(Some(run_id)) => restore(&run_id),
+        Ok(None) => {
+            println!("No runs to undo");
+            Ok(Outcome::NothingToDo)
         }
+        Err(e) => Err(ClndirError::Io("journal".to_string(), e)),
     }
-    let do_delete;
-    if !nowarn {
-        do_delete = is_list_confirmed(&files_to_delete);
-    } else {
-        do_delete = true;
+}
+
+fn restore(run_id: &str) -> Result<Outcome, ClndirError> {
+    let outcomes = journal::restore_run(run_id)
+        .map_err(|e| ClndirError::Io(format!("journal of run {}", run_id), e))?;
+    let mut restored = 0;
+    let mut failed = 0;
+    for (path, outcome) in outcomes {
+        match outcome {
+            RestoreOutcome::Restored => restored += 1,
+            RestoreOutcome::AlreadyRestored => {}
+            RestoreOutcome::Failed(reason) => {
+                failed += 1;
+                eprintln!(
+                    "Error restoring file {}: {}",
+                    path.display().to_string().yellow(),
+                    reason
+                );
+            }
+        }
+    }
+    println!("{} File(s) restored from run {}", restored, run_id);
+    match (restored, failed) {
+        (0, 0) => Ok(Outcome::NothingToDo),
+        (_, 0) => Ok(Outcome::Done),
+        _ => Err(ClndirError::PartialFailure {
+            failed,
+            total: restored + failed,
+        }),
+    }
+}
+
+fn clean_dirs(dirs: &[String], options: &CleanOptions) -> Result<Outcome, ClndirError> {
+    let plan = Planner::new(&options.policy).plan(dirs)?;
+    for warning in plan.dirs.iter().flat_map(|dir| &dir.warnings) {
+        eprintln!("{}", warning);
     }
-    if do_delete {
+    for skipped in &plan.skipped {
         println!(
-            "{} File(s) deleted",
-            delete_files_in_directory(dir, &files_to_delete)
+            "{} has {} free, at least {} wanted, nothing to do",
+            skipped.dir.yellow(),
+            size::format_size(skipped.available),
+            skipped.wanted
         );
     }
-}
-fn is_file_ok_to_delete(file: &FileWithModifiedTime, age: u64, skip: &Vec<String>) -> bool {
-    if file.modified_time.elapsed().unwrap().as_secs() / (SECS_IN_A_DAY) < age {
-        return false;
+    for shortfall in plan.dirs.iter().filter_map(|dir| dir.shortfall) {
+        eprintln!(
+            "Warning: only {} can be freed of the {} needed",
+            size::format_size(shortfall.freeable),
+            size::format_size(shortfall.needed)
+        );
     }
-    if skip.is_empty() {
-        return true;
-    } else {
-        for pattern in skip {
-            if file.name.to_lowercase().contains(&pattern.to_lowercase()) {
-                return false;
+    if plan.candidate_count() == 0 {
+        if options.dry_run && options.format != PlanFormat::Text {
+            print_dry_run(&plan, options);
+            return Ok(Outcome::NothingToDo);
+        }
+        match plan.held_back_count() {
+            0 => println!("No files to remove"),
+            held_back => {
+                if !options.summary_only {
+                    listing::print_files(&plan, options.policy.time_field, options.listing);
+                }
+                println!("No files to remove, {} File(s) held back", held_back);
             }
         }
-        true
+        return Ok(Outcome::NothingToDo);
     }
+    match_and_delete(plan, options)
 }
-fn is_list_confirmed(files: &Vec<FileWithModifiedTime>) -> bool {
-    display_files(files);
 
-    let mut buffer = String::new();
-    print!("\nType {} to delete these files : ", DELETE_COMMAND.red());
-    io::stdout().flush().unwrap();
-    let _ = io::stdin().read_line(&mut buffer);
-
-    if buffer.trim() == DELETE_COMMAND {
-        return true;
+fn match_and_delete(plan: Plan, options: &CleanOptions) -> Result<Outcome, ClndirError> {
+    if options.dry_run {
+        print_dry_run(&plan, options);
+        return Ok(Outcome::Done);
+    }
+    let plan = if options.interactive {
+        match tui::pick_files(&plan, options.policy.time_field) {
+            Ok(Some(ticked)) => keep_ticked(plan, ticked),
+            Ok(None) => return Err(ClndirError::Cancelled),
+            Err(e) => return Err(ClndirError::Io("terminal".to_string(), e)),
+        }
+    } else if options.nowarn || is_list_confirmed(&plan, options) {
+        plan
     } else {
-        println!("Deletion canceled by user");
-        false
+        return Err(ClndirError::Cancelled);
+    };
+
+    let report = Executor::new(options.action, options.archive.clone()).execute(&plan);
+    for dir in &report.dirs {
+        if let Some(e) = &dir.archive_error {
+            eprintln!(
+                "Error archiving files from {}, nothing was removed: {}",
+                dir.dir, e
+            );
+        }
+        if let Some(path) = &dir.archived_to {
+            println!(
+                "{} File(s) archived to {}",
+                dir.removed.len() + dir.failed.len(),
+                path.display().to_string().yellow()
+            );
+        }
+        for failure in &dir.failed {
+            let verb = match report.action {
+                Action::Hardlink | Action::Symlink => "linking",
+                Action::Delete | Action::Trash => "deleting",
+            };
+            eprintln!(
+                "Error {} file {}: {}",
+                verb,
+                failure.name.yellow(),
+                failure.error
+            );
+        }
+        if report.dirs.len() > 1 {
+            println!("{} : {} File(s)", dir.dir.yellow(), dir.removed.len());
+        }
     }
-}
-fn display_files(files: &Vec<FileWithModifiedTime>) {
-    for file in files {
-        let date_time = DateTime::<Utc>::from(file.modified_time);
+    let removed_label = match report.action {
+        Action::Delete => "Deleted",
+        Action::Trash => "Moved to trash",
+        Action::Hardlink => "Replaced by hard links",
+        Action::Symlink => "Replaced by symlinks",
+    };
+    Summary::of_report(&report, &plan, options.policy.time_field).print(removed_label);
+    if let Some(run_id) = &report.run_id {
         println!(
-            "Last Modified {} - {} ",
-            date_time.format(DATE_FORMAT).to_string().green(),
-            file.name.yellow(),
-        )
-    }
-}
-
-fn list_files_with_modified_time(
-    directory_path: &str,
-) -> Result<Vec<FileWithModifiedTime>, io::Error> {
-    let directory = Path::new(directory_path);
-
-    let mut files_with_modified_time = Vec::new();
-
-    for entry in fs::read_dir(directory)? {
-        let entry = entry?;
-        let path = entry.path();
-
-        if path.is_file() {
-            let name = path
-                .file_name()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .to_string();
-            let modified_time = entry.metadata()?.modified()?;
-            files_with_modified_time.push(FileWithModifiedTime {
-                name,
-                modified_time,
-            });
-        }
+            "Run {} journaled, use `clndir restore {}` to undo it",
+            run_id.cyan(),
+            run_id
+        );
+    }
+    if let Some(e) = &report.journal_error {
+        eprintln!("Error writing journal: {}", e);
+    }
+    match report.failed_count() {
+        0 => Ok(Outcome::Done),
+        failed => Err(ClndirError::PartialFailure {
+            failed,
+            total: report.removed_count() + failed,
+        }),
     }
+}
 
-    Ok(files_with_modified_time)
+// Drops the files that were unticked in the interactive picker.
+fn keep_ticked(mut plan: Plan, ticked: Vec<Vec<bool>>) -> Plan {
+    for (dir, ticked) in plan.dirs.iter_mut().zip(ticked) {
+        let mut ticked = ticked.into_iter();
+        dir.candidates.retain(|_| ticked.next().unwrap_or(false));
+    }
+    plan.dirs
+        .retain(|dir| !dir.candidates.is_empty() || !dir.held_back.is_empty());
+    plan
 }
 
-fn delete_files_in_directory(directory_path: &str, files: &Vec<FileWithModifiedTime>) -> u32 {
-    let mut count = 0;
-    for file in files {
-        let file_path = Path::new(directory_path).join(&file.name);
-        if let Err(e) = fs::remove_file(&file_path) {
-            eprintln!("Error deleting file {}: {}", file.name.yellow(), e);
-        } else {
-            count += 1;
+fn print_dry_run(plan: &Plan, options: &CleanOptions) {
+    let time_field = options.policy.time_field;
+    if options.format == PlanFormat::Text {
+        if !options.summary_only {
+            listing::print_files(plan, time_field, options.listing);
         }
+        Summary::of_plan(plan, time_field).print("Would be removed");
+        return;
+    }
+    // Held back files are listed too, with the reason as their rule.
+    let planned: Vec<PlannedFile> = plan
+        .dirs
+        .iter()
+        .flat_map(|dir| {
+            let held_back = dir.held_back.iter().map(|candidate| (candidate, true));
+            let candidates = dir.candidates.iter().map(|candidate| (candidate, false));
+            candidates
+                .chain(held_back)
+                .map(|(candidate, held_back)| PlannedFile {
+                    held_back,
+                    ..PlannedFile::new(
+                        &dir.dir,
+                        &candidate.file.name,
+                        candidate.file.size,
+                        candidate.file.modified_time,
+                        age_in_days(candidate.file.time(time_field)),
+                        &candidate.reason,
+                        candidate.file.kind.label(),
+                    )
+                })
+        })
+        .collect();
+    if let Err(e) = plan::print_plan(&planned, options.format) {
+        eprintln!("Error writing plan: {}", e);
     }
-    count
+}
+
+fn age_in_days(time: SystemTime) -> u64 {
+    time.elapsed().map(|age| age.as_secs()).unwrap_or(0) / SECS_IN_A_DAY
+}
+
+fn is_list_confirmed(plan: &Plan, options: &CleanOptions) -> bool {
+    let time_field = options.policy.time_field;
+    if options.summary_only {
+        Summary::of_plan(plan, time_field).print("To remove");
+    } else {
+        listing::print_files(plan, time_field, options.listing);
+    }
+
+    let mut buffer = String::new();
+    print!("\nType {} to delete these files : ", DELETE_COMMAND.red());
+    io::stdout().flush().unwrap();
+    let _ = io::stdin().read_line(&mut buffer);
+
+    buffer.trim() == DELETE_COMMAND
 }
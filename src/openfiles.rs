@@ -0,0 +1,80 @@
+// Files that running processes hold open or locked, found through /proc so
+// a download still being written or a video being played is not removed
+// from under its reader. Only the processes the user may inspect are seen.
+use std::collections::HashSet;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+const PROC: &str = "/proc";
+const LOCKS: &str = "/proc/locks";
+
+#[derive(Debug, Default)]
+pub struct OpenFiles {
+    // Device and inode of every open or locked file.
+    inodes: HashSet<(u64, u64)>,
+}
+
+impl OpenFiles {
+    // What is open right now. Processes and descriptors that vanish or cannot
+    // be read are passed over.
+    pub fn snapshot() -> OpenFiles {
+        let mut open_files = OpenFiles::default();
+        open_files.add_descriptors();
+        open_files.add_locks();
+        open_files
+    }
+
+    // Whether the file at `path` is open. A link is not followed, since only
+    // the link itself would be removed.
+    pub fn contains(&self, path: &Path) -> bool {
+        fs::symlink_metadata(path)
+            .is_ok_and(|metadata| self.inodes.contains(&(metadata.dev(), metadata.ino())))
+    }
+
+    fn add_descriptors(&mut self) {
+        let Ok(processes) = fs::read_dir(PROC) else {
+            return;
+        };
+        for process in processes.flatten() {
+            let is_pid = process
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.bytes().all(|byte| byte.is_ascii_digit()));
+            if !is_pid {
+                continue;
+            }
+            let Ok(descriptors) = fs::read_dir(process.path().join("fd")) else {
+                continue;
+            };
+            for descriptor in descriptors.flatten() {
+                // Follows the descriptor to the open file itself.
+                if let Ok(metadata) = fs::metadata(descriptor.path()) {
+                    self.inodes.insert((metadata.dev(), metadata.ino()));
+                }
+            }
+        }
+    }
+
+    // Lines of /proc/locks look like
+    // "1: POSIX  ADVISORY  WRITE 1234 08:01:5678 0 EOF", the device given as
+    // hexadecimal major and minor numbers.
+    fn add_locks(&mut self) {
+        let Ok(locks) = fs::read_to_string(LOCKS) else {
+            return;
+        };
+        for line in locks.lines() {
+            let inode = line.split_whitespace().find_map(|field| {
+                let mut parts = field.split(':');
+                let major = u32::from_str_radix(parts.next()?, 16).ok()?;
+                let minor = u32::from_str_radix(parts.next()?, 16).ok()?;
+                let ino = parts.next()?.parse().ok()?;
+                parts
+                    .next()
+                    .is_none()
+                    .then(|| (libc::makedev(major, minor), ino))
+            });
+            self.inodes.extend(inode);
+        }
+    }
+}
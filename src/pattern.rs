@@ -0,0 +1,174 @@
+// Patterns given to --skip and --only.
+//
+// A pattern starting with `re:` is a regular expression matched anywhere in
+// the path relative to the cleaned directory. A pattern containing `*`, `?`
+// or `[` is a glob, matched against the file name, or against the whole
+// relative path when the glob contains a `/`. Anything else matches when it
+// appears anywhere in the relative path.
+use globset::{GlobBuilder, GlobMatcher};
+use regex::{Regex, RegexBuilder};
+use std::fmt;
+
+const REGEX_PREFIX: &str = "re:";
+const GLOB_CHARS: [char; 3] = ['*', '?', '['];
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Substring {
+        text: String,
+        case_sensitive: bool,
+    },
+    Glob {
+        matcher: GlobMatcher,
+        whole_path: bool,
+    },
+    Regex(Regex),
+}
+
+#[derive(Debug)]
+pub struct PatternError {
+    pattern: String,
+    message: String,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid pattern '{}': {}", self.pattern, self.message)
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+impl Pattern {
+    pub fn parse(pattern: &str, case_sensitive: bool) -> Result<Pattern, PatternError> {
+        let error = |message: String| PatternError {
+            pattern: pattern.to_string(),
+            message,
+        };
+        if let Some(expression) = pattern.strip_prefix(REGEX_PREFIX) {
+            RegexBuilder::new(expression)
+                .case_insensitive(!case_sensitive)
+                .build()
+                .map(Pattern::Regex)
+                .map_err(|e| error(e.to_string()))
+        } else if pattern.contains(GLOB_CHARS) {
+            GlobBuilder::new(pattern)
+                .case_insensitive(!case_sensitive)
+                .literal_separator(true)
+                .build()
+                .map(|glob| Pattern::Glob {
+                    matcher: glob.compile_matcher(),
+                    whole_path: pattern.contains('/'),
+                })
+                .map_err(|e| error(e.kind().to_string()))
+        } else {
+            Ok(Pattern::Substring {
+                text: if case_sensitive {
+                    pattern.to_string()
+                } else {
+                    pattern.to_lowercase()
+                },
+                case_sensitive,
+            })
+        }
+    }
+
+    pub fn parse_all(
+        patterns: &[String],
+        case_sensitive: bool,
+    ) -> Result<Vec<Pattern>, PatternError> {
+        patterns
+            .iter()
+            .map(|pattern| Pattern::parse(pattern, case_sensitive))
+            .collect()
+    }
+
+    // `relative_path` is the path of the file relative to the cleaned directory.
+    pub fn is_match(&self, relative_path: &str) -> bool {
+        match self {
+            Pattern::Substring {
+                text,
+                case_sensitive: true,
+            } => relative_path.contains(text.as_str()),
+            Pattern::Substring { text, .. } => relative_path.to_lowercase().contains(text.as_str()),
+            Pattern::Glob {
+                matcher,
+                whole_path: true,
+            } => matcher.is_match(relative_path),
+            Pattern::Glob { matcher, .. } => {
+                let file_name = relative_path.rsplit('/').next().unwrap_or(relative_path);
+                matcher.is_match(file_name)
+            }
+            Pattern::Regex(regex) => regex.is_match(relative_path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, case_sensitive: bool, path: &str) -> bool {
+        Pattern::parse(pattern, case_sensitive)
+            .unwrap()
+            .is_match(path)
+    }
+
+    #[test]
+    fn parse_picks_the_kind_of_pattern() {
+        assert!(matches!(
+            Pattern::parse("report", false),
+            Ok(Pattern::Substring { .. })
+        ));
+        assert!(matches!(
+            Pattern::parse("*.pdf", false),
+            Ok(Pattern::Glob {
+                whole_path: false,
+                ..
+            })
+        ));
+        assert!(matches!(
+            Pattern::parse("docs/*.pdf", false),
+            Ok(Pattern::Glob {
+                whole_path: true,
+                ..
+            })
+        ));
+        assert!(matches!(
+            Pattern::parse("re:^a+$", false),
+            Ok(Pattern::Regex(_))
+        ));
+    }
+
+    #[test]
+    fn parse_rejects_invalid_patterns() {
+        let error = Pattern::parse("re:(", false).unwrap_err();
+        assert!(error.to_string().starts_with("Invalid pattern 're:('"));
+        assert!(Pattern::parse("[z-a]", false).is_err());
+    }
+
+    #[test]
+    fn substrings_match_anywhere_in_the_path() {
+        assert!(matches("REPORT", false, "docs/Report.txt"));
+        assert!(matches("docs", false, "docs/a.txt"));
+        assert!(!matches("REPORT", true, "docs/Report.txt"));
+        assert!(matches("Report", true, "docs/Report.txt"));
+    }
+
+    #[test]
+    fn globs_match_the_name_unless_they_contain_a_slash() {
+        assert!(matches("*.PDF", false, "docs/a.pdf"));
+        assert!(!matches("*.PDF", true, "docs/a.pdf"));
+        assert!(!matches("docs*", false, "docs/a.pdf"));
+        assert!(matches("docs/*.pdf", false, "docs/a.pdf"));
+        assert!(!matches("docs/*.pdf", false, "docs/old/a.pdf"));
+        assert!(matches("docs/**/*.pdf", false, "docs/old/a.pdf"));
+    }
+
+    #[test]
+    fn regexes_match_the_relative_path() {
+        assert!(matches(r"re:\d{4}-\d{2}", false, "backup-2024-01.tar"));
+        assert!(matches("re:^DOCS/", false, "docs/a.pdf"));
+        assert!(!matches("re:^DOCS/", true, "docs/a.pdf"));
+    }
+}
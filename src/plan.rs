@@ -0,0 +1,113 @@
+// Machine readable output of the files a dry run would remove.
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::{self, Write};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum PlanFormat {
+    Text,
+    Json,
+    Csv,
+    Ndjson,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlannedFile {
+    pub dir: String,
+    // Relative to `dir`.
+    pub path: String,
+    pub size: u64,
+    // RFC 3339 in UTC.
+    pub mtime: String,
+    pub age_days: u64,
+    pub rule: String,
+    // file, symlink or broken symlink.
+    #[serde(rename = "type")]
+    pub file_type: String,
+    // Left in place because a process has it open.
+    pub held_back: bool,
+}
+
+impl PlannedFile {
+    pub fn new(
+        dir: &str,
+        path: &str,
+        size: u64,
+        modified_time: SystemTime,
+        age_days: u64,
+        rule: &str,
+        file_type: &str,
+    ) -> Self {
+        PlannedFile {
+            dir: dir.to_string(),
+            path: path.to_string(),
+            size,
+            mtime: DateTime::<Utc>::from(modified_time).to_rfc3339(),
+            age_days,
+            rule: rule.to_string(),
+            file_type: file_type.to_string(),
+            held_back: false,
+        }
+    }
+}
+
+// Writes the plan to stdout. Text output is handled by the caller since it
+// shares the layout of the confirmation list.
+pub fn print_plan(files: &[PlannedFile], format: PlanFormat) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    match format {
+        PlanFormat::Text => {}
+        PlanFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, files)?;
+            writeln!(out)?;
+        }
+        PlanFormat::Ndjson => {
+            for file in files {
+                serde_json::to_writer(&mut out, file)?;
+                writeln!(out)?;
+            }
+        }
+        PlanFormat::Csv => {
+            writeln!(out, "dir,path,size,mtime,age_days,rule,type,held_back")?;
+            for file in files {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{},{},{}",
+                    csv_field(&file.dir),
+                    csv_field(&file.path),
+                    file.size,
+                    file.mtime,
+                    file.age_days,
+                    csv_field(&file.rule),
+                    csv_field(&file.file_type),
+                    file.held_back
+                )?;
+            }
+        }
+    }
+    out.flush()
+}
+
+// Quotes a field when it contains a separator, quote or line break (RFC 4180).
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_field_quotes_only_when_needed() {
+        assert_eq!(csv_field("report.pdf"), "report.pdf");
+        assert_eq!(csv_field("a,b.txt"), "\"a,b.txt\"");
+        assert_eq!(csv_field("say \"hi\".txt"), "\"say \"\"hi\"\".txt\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+}
@@ -0,0 +1,620 @@
+// Picks the files a cleanup policy removes, without touching anything.
+use crate::age::Cutoffs;
+use crate::clndirignore::IgnoreRules;
+use crate::dedupe::{self, KeepPolicy};
+use crate::error::{ClndirError, Warning};
+use crate::fsstat;
+use crate::openfiles::OpenFiles;
+use crate::policy::{CleanupPolicy, EvictBy, FreeSpace, TimeField};
+use crate::retention;
+use crate::scanner::{FileKind, FileRecord, Scan, Scanner};
+use crate::size::SpaceAmount;
+use chrono::Local;
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+// Why a candidate is held back.
+pub const IN_USE: &str = "in use";
+
+// A file picked for removal and why.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub file: FileRecord,
+    // Set in dedupe mode to the name of the copy that is kept.
+    pub duplicate_of: Option<String>,
+    pub reason: String,
+}
+
+// The candidates from one of the directories being cleaned.
+#[derive(Debug)]
+pub struct DirectoryPlan {
+    pub dir: String,
+    pub candidates: Vec<Candidate>,
+    // Set when the candidates cannot free as much space as wanted.
+    pub shortfall: Option<Shortfall>,
+    // Files old enough to go that a skip pattern or .clndirignore kept.
+    pub protected: usize,
+    // Candidates left in place because a process has them open.
+    pub held_back: Vec<Candidate>,
+    // In dedupe mode, the copy kept of each set of duplicates.
+    pub kept: Vec<FileRecord>,
+    // Files and ignore files that could not be read, for the caller to report.
+    pub warnings: Vec<Warning>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Shortfall {
+    pub freeable: u64,
+    pub needed: u64,
+}
+
+// What the policy makes of one scanned file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Verdict {
+    Removable,
+    // Outside the time window or not matched by --only.
+    Excluded,
+    // Would be removed but for a skip pattern or .clndirignore.
+    Protected,
+}
+
+// The directories on one filesystem, which share its free space target.
+struct Filesystem {
+    // Bytes to free on the filesystem.
+    needed: u64,
+    // Index in the plan and bytes over quota of each directory.
+    members: Vec<(usize, Option<u64>)>,
+}
+
+// A directory left alone because its filesystem already has enough free
+// space.
+#[derive(Debug)]
+pub struct Skipped {
+    pub dir: String,
+    pub available: u64,
+    pub wanted: SpaceAmount,
+}
+
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub dirs: Vec<DirectoryPlan>,
+    pub skipped: Vec<Skipped>,
+}
+
+impl Plan {
+    pub fn candidate_count(&self) -> usize {
+        self.dirs.iter().map(|dir| dir.candidates.len()).sum()
+    }
+
+    pub fn held_back_count(&self) -> usize {
+        self.dirs.iter().map(|dir| dir.held_back.len()).sum()
+    }
+
+    pub fn protected_count(&self) -> usize {
+        self.dirs.iter().map(|dir| dir.protected).sum()
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.dirs
+            .iter()
+            .flat_map(|dir| &dir.candidates)
+            .map(|candidate| candidate.file.size)
+            .sum()
+    }
+}
+
+pub struct Planner<'a> {
+    policy: &'a CleanupPolicy,
+    // The policy's time window resolved against the time planning started.
+    cutoffs: Cutoffs,
+    // Files open when planning started.
+    open_files: OpenFiles,
+}
+
+impl<'a> Planner<'a> {
+    pub fn new(policy: &'a CleanupPolicy) -> Planner<'a> {
+        Planner {
+            policy,
+            cutoffs: policy.window.cutoffs(Local::now()),
+            open_files: OpenFiles::snapshot(),
+        }
+    }
+
+    pub fn plan(&self, dirs: &[String]) -> Result<Plan, ClndirError> {
+        let scanner = Scanner::new(
+            self.policy.depth,
+            self.policy.ignore_files_case_sensitive,
+            self.policy.file_types,
+        );
+        let mut plan = Plan::default();
+        // The free space target is for a whole filesystem, so the directories
+        // on one share it. By device.
+        let mut filesystems: HashMap<u64, Filesystem> = HashMap::new();
+        for dir in dirs {
+            let space_needed = match self.policy.free_space {
+                Some(free_space) => match self.free_space_needed(dir, free_space)? {
+                    Ok(needed) => Some(needed),
+                    Err(skipped) => {
+                        plan.skipped.push(skipped);
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            let scan = scanner.scan(dir)?;
+            let Some(needed) = space_needed.filter(|_| self.policy.dedupe.is_none()) else {
+                plan.dirs.push(self.plan_scan(scan, space_needed));
+                continue;
+            };
+            let device = fs::metadata(dir)
+                .map_err(|e| ClndirError::from_io(dir, e))?
+                .dev();
+            let over_quota = self.over_quota(&scan);
+            filesystems
+                .entry(device)
+                .or_insert(Filesystem {
+                    needed,
+                    members: Vec::new(),
+                })
+                .members
+                .push((plan.dirs.len(), over_quota));
+            // Every candidate for now, oldest first, cut down once all the
+            // directories on the filesystem are known.
+            plan.dirs.push(self.plan_scan(scan, Some(u64::MAX)));
+        }
+        for filesystem in filesystems.into_values() {
+            self.share_free_space(&mut plan.dirs, &filesystem);
+        }
+        Ok(plan)
+    }
+
+    // Keeps the oldest candidates across the directories of one filesystem
+    // until they free the bytes it needs. Each directory keeps at least what
+    // its own quota asks for. A shortfall of the filesystem is reported on its
+    // first directory.
+    fn share_free_space(&self, plans: &mut [DirectoryPlan], filesystem: &Filesystem) {
+        let needed = filesystem.needed;
+        let members = &filesystem.members;
+        let eviction_field = self.eviction_field();
+        let mut freed = 0;
+        // How many of each directory's candidates are kept.
+        let mut lengths = Vec::new();
+        for &(index, over_quota) in members {
+            let plan = &mut plans[index];
+            let quota = over_quota.unwrap_or(0);
+            let mut length = 0;
+            let mut bytes = 0;
+            while length < plan.candidates.len() && bytes < quota {
+                bytes += plan.candidates[length].file.size;
+                length += 1;
+            }
+            plan.shortfall = (bytes < quota).then_some(Shortfall {
+                freeable: bytes,
+                needed: quota,
+            });
+            freed += bytes;
+            lengths.push(length);
+        }
+
+        let mut rest = Vec::new();
+        for (member, &(index, _)) in members.iter().enumerate() {
+            for (position, candidate) in plans[index].candidates.iter().enumerate() {
+                if position >= lengths[member] {
+                    rest.push((candidate.file.time(eviction_field), member, position));
+                }
+            }
+        }
+        // Stable, so the candidates of a directory stay in order.
+        rest.sort_by_key(|(time, _, _)| *time);
+        for (_, member, position) in rest {
+            if freed >= needed {
+                break;
+            }
+            freed += plans[members[member].0].candidates[position].file.size;
+            lengths[member] = position + 1;
+        }
+
+        for (&(index, _), length) in members.iter().zip(lengths) {
+            plans[index].candidates.truncate(length);
+        }
+        if freed < needed {
+            if let Some(&(first, _)) = members.first() {
+                plans[first].shortfall = Some(Shortfall {
+                    freeable: freed,
+                    needed,
+                });
+            }
+        }
+    }
+
+    // Picks candidates from a directory that has already been scanned.
+    // `space_needed` is how many bytes the free space target wants removed.
+    pub fn plan_scan(&self, mut scan: Scan, space_needed: Option<u64>) -> DirectoryPlan {
+        // A link could be kept as the copy of a file that is then removed.
+        if self.policy.dedupe.is_some() {
+            scan.files.retain(|file| file.kind == FileKind::File);
+        }
+        let verdicts: Vec<Verdict> = scan
+            .files
+            .iter()
+            .map(|file| self.verdict(file, &scan.ignores))
+            .collect();
+        if let Some(keep) = self.policy.dedupe {
+            let (candidates, kept, warnings) = self.select_duplicates(&scan, &verdicts, keep);
+            let (candidates, held_back) = self.hold_back_open_files(&scan.dir, candidates);
+            scan.warnings.extend(warnings);
+            return DirectoryPlan {
+                dir: scan.dir,
+                candidates,
+                shortfall: None,
+                protected: count_protected(&verdicts, |_| true),
+                held_back,
+                kept,
+                warnings: scan.warnings,
+            };
+        }
+        let policy = self.policy;
+        let over_quota = self.over_quota(&scan);
+        // Retention ranks every file of a series, including protected ones, so
+        // a skipped file still fills its slot.
+        let kept = policy
+            .retention
+            .map(|retention| retention::files_to_keep(&scan.files, retention, policy.time_field))
+            .unwrap_or_default();
+        let protected = count_protected(&verdicts, |index| !kept.contains(&scan.files[index].name));
+        let reason = policy.describe();
+        let candidates: Vec<Candidate> = scan
+            .files
+            .into_iter()
+            .zip(verdicts)
+            .filter(|(file, verdict)| *verdict == Verdict::Removable && !kept.contains(&file.name))
+            .map(|(file, _)| Candidate {
+                file,
+                duplicate_of: None,
+                reason: reason.clone(),
+            })
+            .collect();
+        // Open files free nothing, so they are set aside before the quota
+        // and free space targets pick the files that make up the bytes.
+        let (candidates, held_back) = self.hold_back_open_files(&scan.dir, candidates);
+        let (candidates, shortfall) = match over_quota.into_iter().chain(space_needed).max() {
+            Some(bytes_to_free) => select_oldest(candidates, bytes_to_free, self.eviction_field()),
+            None => (candidates, None),
+        };
+        DirectoryPlan {
+            dir: scan.dir,
+            candidates,
+            shortfall,
+            protected,
+            held_back,
+            kept: Vec::new(),
+            warnings: scan.warnings,
+        }
+    }
+
+    // Splits off the candidates some process has open, so they are still
+    // listed but not removed. Returns the others, then the held back ones.
+    fn hold_back_open_files(
+        &self,
+        dir: &str,
+        candidates: Vec<Candidate>,
+    ) -> (Vec<Candidate>, Vec<Candidate>) {
+        let dir = Path::new(dir);
+        let (held_back, candidates): (Vec<Candidate>, Vec<Candidate>) = candidates
+            .into_iter()
+            .partition(|candidate| self.open_files.contains(&dir.join(&candidate.file.name)));
+        let held_back = held_back
+            .into_iter()
+            .map(|candidate| Candidate {
+                reason: IN_USE.to_string(),
+                ..candidate
+            })
+            .collect();
+        (candidates, held_back)
+    }
+
+    fn verdict(&self, file: &FileRecord, ignores: &IgnoreRules) -> Verdict {
+        let policy = self.policy;
+        let only = &policy.only;
+        if !only.is_empty() && !only.iter().any(|pattern| pattern.is_match(&file.name)) {
+            return Verdict::Excluded;
+        }
+        if !self.cutoffs.contains(file.time(policy.time_field)) {
+            return Verdict::Excluded;
+        }
+        if policy
+            .skip
+            .iter()
+            .any(|pattern| pattern.is_match(&file.name))
+            || ignores.is_protected(&file.name)
+        {
+            Verdict::Protected
+        } else {
+            Verdict::Removable
+        }
+    }
+
+    // Bytes the directory is over its quota. Protected files still count
+    // towards the quota's total.
+    fn over_quota(&self, scan: &Scan) -> Option<u64> {
+        let total_size: u64 = scan.files.iter().map(|file| file.size).sum();
+        self.policy
+            .quota
+            .map(|quota| total_size.saturating_sub(quota.max_total_size))
+    }
+
+    fn eviction_field(&self) -> TimeField {
+        match self.policy.evict_by {
+            EvictBy::Age => self.policy.time_field,
+            EvictBy::Access => TimeField::Atime,
+        }
+    }
+
+    // Bytes to free on the directory's filesystem, or why it is left alone
+    // when it already has enough free space.
+    fn free_space_needed(
+        &self,
+        dir: &str,
+        free_space: FreeSpace,
+    ) -> Result<Result<u64, Skipped>, ClndirError> {
+        let space = fsstat::space(Path::new(dir)).map_err(|e| ClndirError::from_io(dir, e))?;
+        let trigger = free_space.trigger.to_bytes(space.total);
+        if space.available >= trigger {
+            return Ok(Err(Skipped {
+                dir: dir.to_string(),
+                available: space.available,
+                wanted: free_space.trigger,
+            }));
+        }
+        let target = free_space.target.to_bytes(space.total);
+        Ok(Ok(target.saturating_sub(space.available)))
+    }
+
+    // Picks every copy but one of each set of identical files, recording which
+    // copy is kept. Duplicates are listed next to each other.
+    fn select_duplicates(
+        &self,
+        scan: &Scan,
+        verdicts: &[Verdict],
+        keep: KeepPolicy,
+    ) -> (Vec<Candidate>, Vec<FileRecord>, Vec<Warning>) {
+        let policy = self.policy;
+        let removable: Vec<bool> = verdicts
+            .iter()
+            .map(|verdict| *verdict == Verdict::Removable)
+            .collect();
+        let (sets, warnings) = dedupe::find_duplicates(
+            Path::new(&scan.dir),
+            &scan.files,
+            &removable,
+            keep,
+            policy.time_field,
+        );
+
+        let mut selected = Vec::new();
+        let mut kept_copies = Vec::new();
+        for set in sets {
+            kept_copies.push(scan.files[set.kept].clone());
+            let kept = &scan.files[set.kept].name;
+            let mut duplicates: Vec<&FileRecord> = set
+                .duplicates
+                .iter()
+                .map(|&index| &scan.files[index])
+                .collect();
+            duplicates.sort_by(|a, b| a.name.cmp(&b.name));
+            selected.extend(duplicates.into_iter().map(|file| Candidate {
+                file: file.clone(),
+                duplicate_of: Some(kept.clone()),
+                reason: format!("duplicate of {}", kept),
+            }));
+        }
+        (selected, kept_copies, warnings)
+    }
+}
+
+// Picks candidates oldest first until they add up to `bytes_to_free`.
+fn select_oldest(
+    mut candidates: Vec<Candidate>,
+    bytes_to_free: u64,
+    eviction_field: TimeField,
+) -> (Vec<Candidate>, Option<Shortfall>) {
+    candidates.sort_by_key(|candidate| candidate.file.time(eviction_field));
+
+    let mut freed = 0;
+    let mut selected = Vec::new();
+    for candidate in candidates {
+        if freed >= bytes_to_free {
+            break;
+        }
+        freed += candidate.file.size;
+        selected.push(candidate);
+    }
+    let shortfall = (freed < bytes_to_free).then_some(Shortfall {
+        freeable: freed,
+        needed: bytes_to_free,
+    });
+    (selected, shortfall)
+}
+
+// Counts the protected files among those `include` accepts, by index.
+fn count_protected(verdicts: &[Verdict], include: impl Fn(usize) -> bool) -> usize {
+    verdicts
+        .iter()
+        .enumerate()
+        .filter(|(index, verdict)| **verdict == Verdict::Protected && include(*index))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::age::TimeWindow;
+    use crate::policy::{DepthRange, FileTypes, Quota};
+    use std::env;
+    use std::fs::File;
+    use std::path::PathBuf;
+    use std::process;
+    use std::time::{Duration, SystemTime};
+
+    const DAY: Duration = Duration::from_secs(60 * 60 * 24);
+
+    // A fresh directory for one test, removed when it is dropped.
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> TestDir {
+            let dir = env::temp_dir().join(format!("clndir-planner-{}-{}", name, process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TestDir(dir)
+        }
+
+        // Writes `size` bytes to `name`, last modified `days_old` days ago.
+        fn file(&self, name: &str, size: usize, days_old: u32) -> PathBuf {
+            let path = self.0.join(name);
+            fs::write(&path, vec![b'x'; size]).unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(SystemTime::now() - DAY * days_old)
+                .unwrap();
+            path
+        }
+
+        fn path(&self) -> String {
+            self.0.to_string_lossy().to_string()
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn policy() -> CleanupPolicy {
+        CleanupPolicy {
+            window: TimeWindow::default(),
+            time_field: TimeField::Mtime,
+            quota: None,
+            free_space: None,
+            evict_by: EvictBy::Age,
+            retention: None,
+            dedupe: None,
+            skip: Vec::new(),
+            only: Vec::new(),
+            ignore_files_case_sensitive: false,
+            depth: DepthRange { min: 1, max: 1 },
+            file_types: FileTypes::default(),
+        }
+    }
+
+    fn names(candidates: &[Candidate]) -> Vec<&str> {
+        let mut names: Vec<&str> = candidates
+            .iter()
+            .map(|candidate| candidate.file.name.as_str())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn open_files_do_not_count_towards_a_quota() {
+        let dir = TestDir::new("quota");
+        let open = dir.file("a", 4, 30);
+        dir.file("b", 4, 20);
+        dir.file("c", 4, 10);
+        dir.file("d", 8, 1);
+        let _reader = File::open(&open).unwrap();
+
+        let mut policy = policy();
+        policy.quota = Some(Quota { max_total_size: 15 });
+        let plan = Planner::new(&policy).plan(&[dir.path()]).unwrap();
+        let plan = &plan.dirs[0];
+        assert_eq!(names(&plan.held_back), ["a"]);
+        assert_eq!(plan.held_back[0].reason, IN_USE);
+        assert_eq!(names(&plan.candidates), ["b", "c"]);
+        assert!(plan.shortfall.is_none());
+    }
+
+    #[test]
+    fn open_files_make_a_shortfall() {
+        let dir = TestDir::new("shortfall");
+        let open = dir.file("a", 10, 30);
+        dir.file("b", 4, 20);
+        let _reader = File::open(&open).unwrap();
+
+        let mut policy = policy();
+        policy.quota = Some(Quota { max_total_size: 4 });
+        let plan = Planner::new(&policy).plan(&[dir.path()]).unwrap();
+        let plan = &plan.dirs[0];
+        assert_eq!(names(&plan.candidates), ["b"]);
+        let shortfall = plan.shortfall.unwrap();
+        assert_eq!((shortfall.freeable, shortfall.needed), (4, 10));
+    }
+
+    // Plans `dirs` as members of one filesystem that needs `needed` bytes,
+    // with the given bytes over quota for each directory.
+    fn share(dirs: &[&TestDir], over_quota: &[Option<u64>], needed: u64) -> Vec<DirectoryPlan> {
+        let policy = policy();
+        let planner = Planner::new(&policy);
+        let scanner = Scanner::new(
+            policy.depth,
+            policy.ignore_files_case_sensitive,
+            policy.file_types,
+        );
+        let mut plans: Vec<DirectoryPlan> = dirs
+            .iter()
+            .map(|dir| planner.plan_scan(scanner.scan(&dir.path()).unwrap(), Some(u64::MAX)))
+            .collect();
+        let filesystem = Filesystem {
+            needed,
+            members: over_quota.iter().copied().enumerate().collect(),
+        };
+        planner.share_free_space(&mut plans, &filesystem);
+        plans
+    }
+
+    #[test]
+    fn free_space_is_shared_by_the_directories_of_a_filesystem() {
+        let first = TestDir::new("share-first");
+        first.file("a1", 4, 40);
+        first.file("a2", 4, 20);
+        first.file("a3", 4, 5);
+        let second = TestDir::new("share-second");
+        second.file("b1", 4, 30);
+        second.file("b2", 4, 10);
+        second.file("b3", 4, 1);
+
+        // The oldest files go first, wherever they are.
+        let plans = share(&[&first, &second], &[None, None], 16);
+        assert_eq!(names(&plans[0].candidates), ["a1", "a2"]);
+        assert_eq!(names(&plans[1].candidates), ["b1", "b2"]);
+        assert!(plans.iter().all(|plan| plan.shortfall.is_none()));
+
+        // A quota still takes what it needs, and counts towards the target.
+        let plans = share(&[&first, &second], &[None, Some(12)], 16);
+        assert_eq!(names(&plans[0].candidates), ["a1"]);
+        assert_eq!(names(&plans[1].candidates), ["b1", "b2", "b3"]);
+        assert!(plans.iter().all(|plan| plan.shortfall.is_none()));
+    }
+
+    #[test]
+    fn free_space_shortfall_is_reported_on_the_first_directory() {
+        let first = TestDir::new("share-short-first");
+        first.file("a1", 4, 40);
+        let second = TestDir::new("share-short-second");
+        second.file("b1", 4, 30);
+        second.file("b2", 4, 10);
+
+        let plans = share(&[&first, &second], &[None, None], 100);
+        assert_eq!(names(&plans[0].candidates), ["a1"]);
+        assert_eq!(names(&plans[1].candidates), ["b1", "b2"]);
+        let shortfall = plans[0].shortfall.unwrap();
+        assert_eq!((shortfall.freeable, shortfall.needed), (12, 100));
+        assert!(plans[1].shortfall.is_none());
+    }
+}
@@ -0,0 +1,170 @@
+// What a cleanup removes: the time window, space targets, retention rules
+// and patterns that together pick files out of a directory.
+use crate::age::TimeWindow;
+use crate::dedupe::KeepPolicy;
+use crate::pattern::Pattern;
+use crate::retention::RetentionPolicy;
+use crate::size::{self, SpaceAmount};
+use serde::Deserialize;
+
+// Which timestamp a file's age is measured from.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum TimeField {
+    Mtime,
+    Atime,
+    Ctime,
+    // Birth time, where the filesystem records it. Falls back to mtime.
+    Btime,
+    // The most recent of all the above.
+    Newest,
+}
+
+impl TimeField {
+    // As given to --time-field and in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            TimeField::Mtime => "mtime",
+            TimeField::Atime => "atime",
+            TimeField::Ctime => "ctime",
+            TimeField::Btime => "btime",
+            TimeField::Newest => "newest",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TimeField::Mtime => "Last Modified",
+            TimeField::Atime => "Last Accessed",
+            TimeField::Ctime => "Last Changed",
+            TimeField::Btime => "Created",
+            TimeField::Newest => "Last Touched",
+        }
+    }
+}
+
+// Order in which files are removed to meet a quota or free space target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum EvictBy {
+    // Oldest by the policy's time field first.
+    Age,
+    // Least recently accessed first.
+    Access,
+}
+
+// What happens to symbolic links to files and directories.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum SymlinkPolicy {
+    // Links are left alone.
+    #[default]
+    Skip,
+    // Links are judged by their own timestamps, and removed without
+    // touching what they point to.
+    DeleteLink,
+    // Links to files are judged by the file they point to, and only the link
+    // is removed.
+    Follow,
+}
+
+// What happens to symbolic links whose target is missing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum BrokenSymlinks {
+    #[default]
+    Keep,
+    // Judged by their own timestamps like any file.
+    Delete,
+}
+
+// Whether files and directories whose name starts with a dot are cleaned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Hidden {
+    #[default]
+    Include,
+    Exclude,
+}
+
+// Which kinds of directory entries can be removed. FIFOs, sockets and device
+// nodes never are.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileTypes {
+    pub symlinks: SymlinkPolicy,
+    pub broken_symlinks: BrokenSymlinks,
+    pub hidden: Hidden,
+}
+
+// Size budget for each directory being cleaned.
+#[derive(Debug, Clone, Copy)]
+pub struct Quota {
+    pub max_total_size: u64,
+}
+
+// Free space wanted on the filesystem of each directory being cleaned.
+#[derive(Debug, Clone, Copy)]
+pub struct FreeSpace {
+    // Cleaning starts when less than this is free.
+    pub trigger: SpaceAmount,
+    // Files are removed until this much is free.
+    pub target: SpaceAmount,
+}
+
+// Limits on how deep into the directory tree files are collected.
+#[derive(Debug, Clone, Copy)]
+pub struct DepthRange {
+    pub min: usize,
+    pub max: usize,
+}
+
+#[derive(Debug)]
+pub struct CleanupPolicy {
+    pub window: TimeWindow,
+    pub time_field: TimeField,
+    pub quota: Option<Quota>,
+    pub free_space: Option<FreeSpace>,
+    pub evict_by: EvictBy,
+    pub retention: Option<RetentionPolicy>,
+    // Set to remove duplicate files, keeping one copy picked by this.
+    pub dedupe: Option<KeepPolicy>,
+    pub skip: Vec<Pattern>,
+    pub only: Vec<Pattern>,
+    // Whether .clndirignore files match case. Skip and only patterns carry
+    // their own flag.
+    pub ignore_files_case_sensitive: bool,
+    pub depth: DepthRange,
+    pub file_types: FileTypes,
+}
+
+impl CleanupPolicy {
+    // Describes why files are picked, as given for each candidate of a plan.
+    pub fn describe(&self) -> String {
+        let eviction_order = match self.evict_by {
+            EvictBy::Age => "oldest",
+            EvictBy::Access => "least recently accessed",
+        };
+        if let Some(quota) = self.quota {
+            format!(
+                "{} first to fit {}",
+                eviction_order,
+                size::format_size(quota.max_total_size)
+            )
+        } else if let Some(free_space) = self.free_space {
+            format!(
+                "{} first to reach {} free",
+                eviction_order, free_space.target
+            )
+        } else if let Some(policy) = self.retention {
+            format!("not kept by {}", policy.describe())
+        } else if self.time_field == TimeField::Mtime {
+            self.window.describe()
+        } else {
+            format!("{} by {}", self.window.describe(), self.time_field.name())
+        }
+    }
+}
@@ -0,0 +1,214 @@
+// Grandfather-father-son retention for series of files such as dated
+// backups and exports.
+//
+// Files belong to the same series when they sit in the same directory and
+// their names only differ in their digits, so backup-2024-01-31.tar.gz and
+// backup-2024-02-29.tar.gz form one series. Within a series, each rule keeps
+// the newest file of each of its most recent N periods, and a file is kept
+// when any rule keeps it.
+use crate::policy::TimeField;
+use crate::scanner::FileRecord;
+use chrono::{DateTime, Datelike, Local};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetentionPolicy {
+    pub last: usize,
+    pub daily: usize,
+    pub weekly: usize,
+    pub monthly: usize,
+    pub yearly: usize,
+}
+
+impl RetentionPolicy {
+    pub fn describe(&self) -> String {
+        [
+            ("last", self.last),
+            ("daily", self.daily),
+            ("weekly", self.weekly),
+            ("monthly", self.monthly),
+            ("yearly", self.yearly),
+        ]
+        .iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(rule, count)| format!("keep-{} {}", rule, count))
+        .collect::<Vec<String>>()
+        .join(", ")
+    }
+}
+
+// Returns the names of the files the policy keeps.
+pub fn files_to_keep(
+    files: &[FileRecord],
+    policy: RetentionPolicy,
+    time_field: TimeField,
+) -> HashSet<String> {
+    // Keyed by directory as well, since digits in directory names such as
+    // 2023/ and 2024/ must not merge their series.
+    let mut series: HashMap<(PathBuf, String), Vec<&FileRecord>> = HashMap::new();
+    for file in files {
+        let path = Path::new(&file.name);
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        series
+            .entry((dir, series_key(&file_name)))
+            .or_default()
+            .push(file);
+    }
+
+    let mut kept = HashSet::new();
+    for mut members in series.into_values() {
+        members.sort_by_key(|file| Reverse(file.time(time_field)));
+        let times: Vec<DateTime<Local>> = members
+            .iter()
+            .map(|file| DateTime::<Local>::from(file.time(time_field)))
+            .collect();
+
+        let mut keep = |indexes: Vec<usize>| {
+            for index in indexes {
+                kept.insert(members[index].name.clone());
+            }
+        };
+        keep((0..policy.last.min(members.len())).collect());
+        keep(newest_per_period(&times, policy.daily, |t| {
+            (t.year(), t.ordinal())
+        }));
+        keep(newest_per_period(&times, policy.weekly, |t| {
+            (t.iso_week().year(), t.iso_week().week())
+        }));
+        keep(newest_per_period(&times, policy.monthly, |t| {
+            (t.year(), t.month())
+        }));
+        keep(newest_per_period(&times, policy.yearly, |t| (t.year(), 0)));
+    }
+    kept
+}
+
+// `times` is sorted newest first. Returns the index of the first file seen
+// in each of the first `count` distinct periods.
+fn newest_per_period<F>(times: &[DateTime<Local>], count: usize, period: F) -> Vec<usize>
+where
+    F: Fn(&DateTime<Local>) -> (i32, u32),
+{
+    let mut indexes = Vec::new();
+    let mut last_period = None;
+    for (index, time) in times.iter().enumerate() {
+        if indexes.len() == count {
+            break;
+        }
+        let current = period(time);
+        if last_period != Some(current) {
+            indexes.push(index);
+            last_period = Some(current);
+        }
+    }
+    indexes
+}
+
+// Replaces every run of digits with # so the members of a series share a key.
+fn series_key(name: &str) -> String {
+    let mut key = String::with_capacity(name.len());
+    let mut in_digits = false;
+    for c in name.chars() {
+        if c.is_ascii_digit() {
+            if !in_digits {
+                key.push('#');
+            }
+            in_digits = true;
+        } else {
+            key.push(c);
+            in_digits = false;
+        }
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::FileKind;
+    use chrono::TimeZone;
+
+    fn local(year: i32, month: u32, day: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(year, month, day, 12, 0, 0).unwrap()
+    }
+
+    fn record(name: &str, time: DateTime<Local>) -> FileRecord {
+        FileRecord {
+            name: name.to_string(),
+            kind: FileKind::File,
+            modified_time: time.into(),
+            accessed_time: time.into(),
+            changed_time: time.into(),
+            birth_time: None,
+            size: 0,
+        }
+    }
+
+    #[test]
+    fn series_key_replaces_runs_of_digits() {
+        assert_eq!(
+            series_key("backup-2024-01-31.tar.gz"),
+            "backup-#-#-#.tar.gz"
+        );
+        assert_eq!(series_key("db7.sql"), "db#.sql");
+        assert_eq!(series_key("notes.txt"), "notes.txt");
+        assert_eq!(series_key("mp3"), "mp#");
+    }
+
+    #[test]
+    fn newest_per_period_keeps_the_first_of_each_period() {
+        let times = [
+            local(2024, 3, 5),
+            local(2024, 3, 1),
+            local(2024, 2, 20),
+            local(2024, 2, 10),
+            local(2024, 1, 31),
+        ];
+        let month = |t: &DateTime<Local>| (t.year(), t.month());
+        assert_eq!(newest_per_period(&times, 2, month), vec![0, 2]);
+        assert_eq!(newest_per_period(&times, 5, month), vec![0, 2, 4]);
+        assert!(newest_per_period(&times, 0, month).is_empty());
+    }
+
+    #[test]
+    fn files_to_keep_applies_every_rule() {
+        let files = [
+            record("backup-04.tar", local(2024, 3, 5)),
+            record("backup-03.tar", local(2024, 3, 1)),
+            record("backup-02.tar", local(2024, 2, 20)),
+            record("backup-01.tar", local(2024, 1, 31)),
+            record("notes.txt", local(2020, 1, 1)),
+        ];
+        let policy = RetentionPolicy {
+            last: 1,
+            monthly: 2,
+            ..RetentionPolicy::default()
+        };
+        let kept = files_to_keep(&files, policy, TimeField::Mtime);
+        let expected = ["backup-04.tar", "backup-02.tar", "notes.txt"];
+        assert_eq!(kept, expected.iter().map(|name| name.to_string()).collect());
+    }
+
+    #[test]
+    fn series_do_not_span_directories() {
+        let files = [
+            record("2023/backup-01.tar", local(2023, 5, 1)),
+            record("2023/backup-02.tar", local(2023, 4, 1)),
+            record("2024/backup-01.tar", local(2024, 5, 1)),
+            record("2024/backup-02.tar", local(2024, 4, 1)),
+        ];
+        let policy = RetentionPolicy {
+            last: 1,
+            ..RetentionPolicy::default()
+        };
+        let kept = files_to_keep(&files, policy, TimeField::Mtime);
+        let expected = ["2023/backup-01.tar", "2024/backup-01.tar"];
+        assert_eq!(kept, expected.iter().map(|name| name.to_string()).collect());
+    }
+}
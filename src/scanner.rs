@@ -0,0 +1,248 @@
+// Walks a directory and records the size and timestamps of its files.
+use crate::clndirignore::{IgnoreRules, IGNORE_FILE_NAME};
+use crate::error::{ClndirError, Warning};
+use crate::policy::{BrokenSymlinks, DepthRange, FileTypes, Hidden, SymlinkPolicy, TimeField};
+use std::fs::{self, Metadata};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// What a directory entry picked for cleaning is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileKind {
+    File,
+    Symlink,
+    BrokenSymlink,
+}
+
+impl FileKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            FileKind::File => "file",
+            FileKind::Symlink => "symlink",
+            FileKind::BrokenSymlink => "broken symlink",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FileRecord {
+    // Path relative to the directory being cleaned.
+    pub name: String,
+    pub kind: FileKind,
+    pub modified_time: SystemTime,
+    pub accessed_time: SystemTime,
+    // Inode change time.
+    pub changed_time: SystemTime,
+    // None when the filesystem does not record birth time.
+    pub birth_time: Option<SystemTime>,
+    pub size: u64,
+}
+
+impl FileRecord {
+    // `metadata` is the link's own for links that are not followed.
+    pub fn from_metadata(
+        name: String,
+        kind: FileKind,
+        metadata: &Metadata,
+    ) -> io::Result<FileRecord> {
+        Ok(FileRecord {
+            name,
+            kind,
+            modified_time: metadata.modified()?,
+            accessed_time: metadata.accessed()?,
+            changed_time: unix_time(metadata.ctime(), metadata.ctime_nsec()),
+            // Uses statx, so this is only available where the kernel and
+            // filesystem support it.
+            birth_time: metadata.created().ok(),
+            size: metadata.len(),
+        })
+    }
+
+    pub fn time(&self, field: TimeField) -> SystemTime {
+        match field {
+            TimeField::Mtime => self.modified_time,
+            TimeField::Atime => self.accessed_time,
+            TimeField::Ctime => self.changed_time,
+            TimeField::Btime => self.birth_time.unwrap_or(self.modified_time),
+            TimeField::Newest => [self.accessed_time, self.changed_time]
+                .into_iter()
+                .chain(self.birth_time)
+                .fold(self.modified_time, SystemTime::max),
+        }
+    }
+}
+
+// The files found in one directory, with the .clndirignore rules loaded on
+// the way that protect some of them.
+#[derive(Debug)]
+pub struct Scan {
+    pub dir: String,
+    pub files: Vec<FileRecord>,
+    pub ignores: IgnoreRules,
+    // Problems met on the way that did not stop the scan.
+    pub warnings: Vec<Warning>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Scanner {
+    depth: DepthRange,
+    // Whether .clndirignore patterns match case sensitively.
+    case_sensitive: bool,
+    file_types: FileTypes,
+}
+
+impl Scanner {
+    pub fn new(depth: DepthRange, case_sensitive: bool, file_types: FileTypes) -> Scanner {
+        Scanner {
+            depth,
+            case_sensitive,
+            file_types,
+        }
+    }
+
+    pub fn scan(&self, dir: &str) -> Result<Scan, ClndirError> {
+        let mut scan = Scan {
+            dir: dir.to_string(),
+            files: Vec::new(),
+            ignores: IgnoreRules::new(Path::new(dir), self.case_sensitive),
+            warnings: Vec::new(),
+        };
+        self.collect_files(Path::new(dir), &PathBuf::new(), 1, &mut scan)?;
+        Ok(scan)
+    }
+
+    // Records the entry at `path`, or returns None when it is of a kind the
+    // policy leaves alone. Directories, FIFOs, sockets and device nodes are
+    // always left alone.
+    pub fn record(&self, path: &Path, name: String) -> io::Result<Option<FileRecord>> {
+        let file_types = self.file_types;
+        if file_types.hidden == Hidden::Exclude && is_hidden(&name) {
+            return Ok(None);
+        }
+        let metadata = fs::symlink_metadata(path)?;
+        if metadata.file_type().is_file() {
+            return FileRecord::from_metadata(name, FileKind::File, &metadata).map(Some);
+        }
+        if !metadata.file_type().is_symlink() {
+            return Ok(None);
+        }
+        let target = match fs::metadata(path) {
+            Ok(target) => target,
+            Err(e) if is_broken_link(&e) => {
+                return match file_types.broken_symlinks {
+                    BrokenSymlinks::Keep => Ok(None),
+                    BrokenSymlinks::Delete => {
+                        FileRecord::from_metadata(name, FileKind::BrokenSymlink, &metadata)
+                            .map(Some)
+                    }
+                };
+            }
+            // The link may work for someone else, so it is not broken.
+            Err(_) => return Ok(None),
+        };
+        match file_types.symlinks {
+            SymlinkPolicy::Skip => Ok(None),
+            SymlinkPolicy::DeleteLink => {
+                FileRecord::from_metadata(name, FileKind::Symlink, &metadata).map(Some)
+            }
+            // Judged by the target's times, but removing the link only frees
+            // the link itself.
+            SymlinkPolicy::Follow if target.is_file() => {
+                let record = FileRecord::from_metadata(name, FileKind::Symlink, &target)?;
+                Ok(Some(FileRecord {
+                    size: metadata.len(),
+                    ..record
+                }))
+            }
+            SymlinkPolicy::Follow => Ok(None),
+        }
+    }
+
+    // Walks `directory` collecting files whose depth falls inside the range,
+    // and loading every .clndirignore found on the way, whatever its depth.
+    // Symlinked directories are not followed so a link cannot escape the tree.
+    // Only an unreadable root fails the scan: subdirectories and files that
+    // cannot be read are skipped with a warning.
+    fn collect_files(
+        &self,
+        directory: &Path,
+        relative: &Path,
+        current_depth: usize,
+        scan: &mut Scan,
+    ) -> Result<(), ClndirError> {
+        let warning = |path: &Path, e: io::Error| Warning {
+            path: path.display().to_string(),
+            message: e.to_string(),
+        };
+        if directory.join(IGNORE_FILE_NAME).is_file() {
+            let warnings = scan.ignores.add_ignore_file(relative);
+            scan.warnings.extend(warnings);
+        }
+        let entries = match fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(e) if current_depth == 1 => {
+                return Err(ClndirError::from_io(&directory.display().to_string(), e));
+            }
+            Err(e) => {
+                scan.warnings.push(warning(directory, e));
+                return Ok(());
+            }
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    scan.warnings.push(warning(directory, e));
+                    continue;
+                }
+            };
+            let path = entry.path();
+            let relative_path = relative.join(entry.file_name());
+            let name = relative_path.to_string_lossy().to_string();
+
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(e) => {
+                    scan.warnings.push(warning(&path, e));
+                    continue;
+                }
+            };
+            if file_type.is_dir() {
+                let excluded = self.file_types.hidden == Hidden::Exclude && is_hidden(&name);
+                if current_depth < self.depth.max && !excluded {
+                    self.collect_files(&path, &relative_path, current_depth + 1, scan)?;
+                }
+            } else if current_depth >= self.depth.min {
+                match self.record(&path, name) {
+                    Ok(record) => scan.files.extend(record),
+                    Err(e) => scan.warnings.push(warning(&path, e)),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Whether the file or one of the directories it is in starts with a dot.
+fn is_hidden(name: &str) -> bool {
+    Path::new(name)
+        .components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+}
+
+// Missing targets and loops of links make a link broken.
+fn is_broken_link(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::NotFound || e.raw_os_error() == Some(libc::ELOOP)
+}
+
+fn unix_time(secs: i64, nsecs: i64) -> SystemTime {
+    let whole_secs = Duration::from_secs(secs.unsigned_abs());
+    let nanos = Duration::from_nanos(nsecs as u64);
+    if secs >= 0 {
+        UNIX_EPOCH + whole_secs + nanos
+    } else {
+        UNIX_EPOCH - whole_secs + nanos
+    }
+}
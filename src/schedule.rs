@@ -0,0 +1,272 @@
+// `clndir schedule`: systemd user timers that run a profile unattended, or
+// the equivalent crontab line. Units are named clndir-<profile> and live in
+// $XDG_CONFIG_HOME/systemd/user.
+use crate::config::Config;
+use crate::Outcome;
+use clap::Subcommand;
+use clndir::error::EXIT_NOTHING_TO_DO;
+use clndir::{xdg, ClndirError};
+use colored::*;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const UNIT_PREFIX: &str = "clndir-";
+// Calendar shorthands systemd understands, with the crontab schedule that
+// fires at the same moments.
+const CRON_SCHEDULES: [(&str, &str); 5] = [
+    ("hourly", "0 * * * *"),
+    ("daily", "0 0 * * *"),
+    ("weekly", "0 0 * * 1"),
+    ("monthly", "0 0 1 * *"),
+    ("yearly", "0 0 1 1 *"),
+];
+
+#[derive(Subcommand)]
+pub enum ScheduleCommand {
+    /// Install and start a systemd user timer that runs a profile
+    Install {
+        #[arg(long)]
+        profile: String,
+        /// hourly, daily, weekly, monthly, yearly or any systemd OnCalendar expression
+        #[arg(long, default_value = "daily")]
+        every: String,
+        /// Print an equivalent crontab line instead of installing a timer
+        #[arg(long)]
+        cron: bool,
+    },
+    /// List the installed timers
+    List,
+    /// Stop and remove the timer of a profile
+    Remove {
+        #[arg(long)]
+        profile: String,
+    },
+}
+
+pub fn schedule(command: &ScheduleCommand) -> Result<Outcome, ClndirError> {
+    match command {
+        ScheduleCommand::Install {
+            profile,
+            every,
+            cron,
+        } => {
+            check_profile(profile)?;
+            if *cron {
+                print_cron_line(profile, every)
+            } else {
+                install(profile, every)
+            }
+        }
+        ScheduleCommand::List => list(),
+        ScheduleCommand::Remove { profile } => remove(profile),
+    }
+}
+
+// The profile must exist, and its name must be usable in a unit name.
+fn check_profile(profile: &str) -> Result<(), ClndirError> {
+    check_profile_name(profile)?;
+    let config = Config::load().map_err(|e| ClndirError::Config(e.to_string()))?;
+    config
+        .profile(profile)
+        .map_err(|e| ClndirError::Config(e.to_string()))?;
+    Ok(())
+}
+
+// Keeps the name from reaching outside the unit directory, as ../x would.
+fn check_profile_name(profile: &str) -> Result<(), ClndirError> {
+    let valid_name = !profile.is_empty()
+        && profile
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c));
+    if !valid_name {
+        return Err(ClndirError::InvalidArgument(format!(
+            "Profile name '{}' can only be scheduled if it is made of letters, digits, '_', '.' and '-'",
+            profile
+        )));
+    }
+    Ok(())
+}
+
+fn install(profile: &str, every: &str) -> Result<Outcome, ClndirError> {
+    check_calendar(every)?;
+    let dir = unit_dir()?;
+    let unit = format!("{}{}", UNIT_PREFIX, profile);
+    let write = |path: PathBuf, contents: String| {
+        fs::write(&path, contents).map_err(|e| ClndirError::Io(path.display().to_string(), e))
+    };
+    fs::create_dir_all(&dir).map_err(|e| ClndirError::Io(dir.display().to_string(), e))?;
+    // A run with nothing to clean exits with EXIT_NOTHING_TO_DO, which must
+    // not mark the service as failed.
+    write(
+        dir.join(format!("{}.service", unit)),
+        format!(
+            "[Unit]\nDescription=Clean files of the clndir profile {profile}\n\n\
+             [Service]\nType=oneshot\nExecStart={command}\n\
+             SuccessExitStatus={EXIT_NOTHING_TO_DO}\n",
+            command = run_command(profile, false)?,
+        ),
+    )?;
+    write(
+        dir.join(format!("{}.timer", unit)),
+        format!(
+            "[Unit]\nDescription=Run the clndir profile {profile} {every}\n\n\
+             [Timer]\nOnCalendar={every}\nPersistent=true\n\n\
+             [Install]\nWantedBy=timers.target\n"
+        ),
+    )?;
+    println!("Installed {}.timer in {}", unit.yellow(), dir.display());
+
+    let timer = format!("{}.timer", unit);
+    if systemctl(&["daemon-reload"]) && systemctl(&["enable", "--now", &timer]) {
+        println!("Timer started, it runs {}", every.cyan());
+    } else {
+        eprintln!(
+            "Warning: the timer is installed but not running, start it with `systemctl --user enable --now {}`",
+            timer
+        );
+    }
+    Ok(Outcome::Done)
+}
+
+// Rejects what systemd would not parse as an OnCalendar expression. Where
+// systemd-analyze is missing the expression is taken as it is.
+fn check_calendar(every: &str) -> Result<(), ClndirError> {
+    let invalid = || ClndirError::InvalidArgument(format!("Invalid --every '{}'", every));
+    if every.is_empty() || every.contains(char::is_control) {
+        return Err(invalid());
+    }
+    let checked = Command::new("systemd-analyze")
+        .args(["calendar", every])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    match checked {
+        Ok(status) if !status.success() => Err(invalid()),
+        _ => Ok(()),
+    }
+}
+
+fn print_cron_line(profile: &str, every: &str) -> Result<Outcome, ClndirError> {
+    let schedule = CRON_SCHEDULES
+        .iter()
+        .find(|(name, _)| *name == every)
+        .map(|(_, schedule)| *schedule)
+        .ok_or_else(|| {
+            ClndirError::InvalidArgument(format!(
+                "--cron only knows hourly, daily, weekly, monthly and yearly, not '{}'",
+                every
+            ))
+        })?;
+    println!("{} {}", schedule, run_command(profile, true)?);
+    Ok(Outcome::Done)
+}
+
+fn list() -> Result<Outcome, ClndirError> {
+    let dir = unit_dir()?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            println!("No timers installed");
+            return Ok(Outcome::NothingToDo);
+        }
+        Err(e) => return Err(ClndirError::Io(dir.display().to_string(), e)),
+    };
+    let mut timers: Vec<(String, String)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let profile = file_name
+                .strip_prefix(UNIT_PREFIX)?
+                .strip_suffix(".timer")?
+                .to_string();
+            let contents = fs::read_to_string(entry.path()).ok()?;
+            let every = contents
+                .lines()
+                .find_map(|line| line.strip_prefix("OnCalendar="))
+                .unwrap_or("?")
+                .to_string();
+            Some((profile, every))
+        })
+        .collect();
+    if timers.is_empty() {
+        println!("No timers installed");
+        return Ok(Outcome::NothingToDo);
+    }
+    timers.sort();
+    for (profile, every) in timers {
+        println!("{} {}", profile.yellow(), every.cyan());
+    }
+    Ok(Outcome::Done)
+}
+
+// The profile may be gone from the config by now, so only its name is
+// checked.
+fn remove(profile: &str) -> Result<Outcome, ClndirError> {
+    check_profile_name(profile)?;
+    let dir = unit_dir()?;
+    let unit = format!("{}{}", UNIT_PREFIX, profile);
+    let timer = dir.join(format!("{}.timer", unit));
+    if !timer.exists() {
+        println!("No timer installed for profile {}", profile);
+        return Ok(Outcome::NothingToDo);
+    }
+    // The timer may never have been started, so a failure here is fine.
+    systemctl(&["disable", "--now", &format!("{}.timer", unit)]);
+    for path in [timer, dir.join(format!("{}.service", unit))] {
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(ClndirError::Io(path.display().to_string(), e)),
+        }
+    }
+    systemctl(&["daemon-reload"]);
+    println!("Removed {}.timer", unit.yellow());
+    Ok(Outcome::Done)
+}
+
+fn unit_dir() -> Result<PathBuf, ClndirError> {
+    xdg::config_home()
+        .map(|config| config.join("systemd/user"))
+        .map_err(|e| ClndirError::Io("XDG_CONFIG_HOME".to_string(), e))
+}
+
+// The command the timer runs. --nowarn keeps it from waiting on a prompt
+// nobody will answer.
+fn run_command(profile: &str, for_shell: bool) -> Result<String, ClndirError> {
+    let exe = env::current_exe()
+        .map_err(|e| ClndirError::Io("path of the clndir executable".to_string(), e))?;
+    let exe = quote(&exe, for_shell);
+    Ok(format!("{} run {} --nowarn", exe, profile))
+}
+
+// Quotes a path with spaces, for systemd or for sh. Profile names are
+// already restricted to characters that need no quoting.
+fn quote(path: &Path, for_shell: bool) -> String {
+    let path = path.display().to_string();
+    if !path.contains(|c: char| c.is_whitespace() || "'\"\\$`%".contains(c)) {
+        return path;
+    }
+    if for_shell {
+        format!("'{}'", path.replace('\'', "'\\''"))
+    } else {
+        let escaped = path
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "%%")
+            .replace('$', "$$");
+        format!("\"{}\"", escaped)
+    }
+}
+
+// Runs `systemctl --user` and says whether it succeeded. Its own messages
+// are left on stderr.
+fn systemctl(args: &[&str]) -> bool {
+    Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .status()
+        .is_ok_and(|status| status.success())
+}
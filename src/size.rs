@@ -0,0 +1,108 @@
+// Human readable byte sizes, using binary units (1K = 1024 bytes).
+use std::fmt;
+
+const UNITS: [&str; 5] = ["", "K", "M", "G", "T"];
+const UNIT_SIZE: f64 = 1024.0;
+
+// Parses sizes such as 20G, 1.5T, 500M, 100k or a plain number of bytes.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let number_end = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(number_end);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("'{}' is not a size such as 20G or 500M", value))?;
+
+    let unit = unit.trim().to_uppercase();
+    let unit = unit.trim_end_matches("IB").trim_end_matches('B');
+    let power = UNITS
+        .iter()
+        .position(|&u| u == unit)
+        .ok_or_else(|| format!("Unknown size unit in '{}', use K, M, G or T", value))?;
+    Ok((number * UNIT_SIZE.powi(power as i32)) as u64)
+}
+
+pub fn format_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= UNIT_SIZE && unit < UNITS.len() - 1 {
+        size /= UNIT_SIZE;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}B", bytes)
+    } else {
+        format!("{:.1}{}", size, UNITS[unit])
+    }
+}
+
+// An amount of disk space, either absolute or relative to a filesystem's size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpaceAmount {
+    Bytes(u64),
+    Percent(f64),
+}
+
+impl SpaceAmount {
+    pub fn to_bytes(self, filesystem_size: u64) -> u64 {
+        match self {
+            SpaceAmount::Bytes(bytes) => bytes,
+            SpaceAmount::Percent(percent) => (filesystem_size as f64 * percent / 100.0) as u64,
+        }
+    }
+}
+
+impl fmt::Display for SpaceAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpaceAmount::Bytes(bytes) => write!(f, "{}", format_size(*bytes)),
+            SpaceAmount::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+// Parses either a size such as 50G or a percentage such as 10%.
+pub fn parse_space_amount(value: &str) -> Result<SpaceAmount, String> {
+    match value.trim().strip_suffix('%') {
+        Some(percent) => match percent.trim().parse::<f64>() {
+            Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(SpaceAmount::Percent(percent)),
+            _ => Err(format!(
+                "'{}' is not a percentage between 0% and 100%",
+                value
+            )),
+        },
+        None => parse_size(value).map(SpaceAmount::Bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size_reads_binary_units() {
+        assert_eq!(parse_size("100"), Ok(100));
+        assert_eq!(parse_size("100k"), Ok(100 * 1024));
+        assert_eq!(parse_size("1.5K"), Ok(1536));
+        assert_eq!(parse_size("500MB"), Ok(500 * 1024 * 1024));
+        assert_eq!(parse_size("20 GiB"), Ok(20 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("2T"), Ok(2 * 1024_u64.pow(4)));
+    }
+
+    #[test]
+    fn parse_size_rejects_bad_values() {
+        assert!(parse_size("").is_err());
+        assert!(parse_size("G").is_err());
+        assert!(parse_size("5X").is_err());
+        assert!(parse_size("1.2.3M").is_err());
+    }
+
+    #[test]
+    fn format_size_picks_the_largest_unit() {
+        assert_eq!(format_size(512), "512B");
+        assert_eq!(format_size(1536), "1.5K");
+        assert_eq!(format_size(20 * 1024 * 1024 * 1024), "20.0G");
+    }
+}
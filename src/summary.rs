@@ -0,0 +1,134 @@
+// The table shown after a run: what was freed, what was kept and what failed,
+// with the removed files broken down by extension and by age.
+use crate::age_in_days;
+use crate::listing::extension_label;
+use clndir::executor::Report;
+use clndir::planner::Plan;
+use clndir::size::format_size;
+use clndir::{FileRecord, TimeField};
+use colored::*;
+use std::collections::HashMap;
+
+// Upper bound in days of each age bucket, and its label.
+const AGE_BUCKETS: [(u64, &str); 5] = [
+    (7, "Under a week"),
+    (30, "1 week to 1 month"),
+    (182, "1 to 6 months"),
+    (365, "6 months to 1 year"),
+    (u64::MAX, "Over a year"),
+];
+const KEPT_LABEL: &str = "Kept by skip rules";
+const HELD_BACK_LABEL: &str = "Held back, in use";
+const ERRORS_LABEL: &str = "Errors";
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Tally {
+    files: usize,
+    bytes: u64,
+}
+
+impl Tally {
+    fn add(&mut self, bytes: u64) {
+        self.files += 1;
+        self.bytes += bytes;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    removed: Tally,
+    protected: usize,
+    held_back: usize,
+    errors: usize,
+    by_extension: HashMap<String, Tally>,
+    by_age: [Tally; AGE_BUCKETS.len()],
+}
+
+impl Summary {
+    // What a run did.
+    pub fn of_report(report: &Report, plan: &Plan, time_field: TimeField) -> Summary {
+        let mut summary = Summary {
+            protected: plan.protected_count(),
+            held_back: plan.held_back_count(),
+            errors: report.failed_count(),
+            ..Summary::default()
+        };
+        for removed in report.dirs.iter().flat_map(|dir| &dir.removed) {
+            summary.add(&removed.file, removed.size, time_field);
+        }
+        summary
+    }
+
+    // What a plan would do, for dry runs and confirmation.
+    pub fn of_plan(plan: &Plan, time_field: TimeField) -> Summary {
+        let mut summary = Summary {
+            protected: plan.protected_count(),
+            held_back: plan.held_back_count(),
+            ..Summary::default()
+        };
+        for candidate in plan.dirs.iter().flat_map(|dir| &dir.candidates) {
+            summary.add(&candidate.file, candidate.file.size, time_field);
+        }
+        summary
+    }
+
+    fn add(&mut self, file: &FileRecord, bytes: u64, time_field: TimeField) {
+        self.removed.add(bytes);
+        self.by_extension
+            .entry(extension_label(&file.name))
+            .or_default()
+            .add(bytes);
+        let age = age_in_days(file.time(time_field));
+        let bucket = AGE_BUCKETS
+            .iter()
+            .position(|(days, _)| age < *days)
+            .unwrap_or(AGE_BUCKETS.len() - 1);
+        self.by_age[bucket].add(bytes);
+    }
+
+    // `removed_label` says what happened to the removed files, such as
+    // "Deleted" or "Would be removed".
+    pub fn print(&self, removed_label: &str) {
+        // Wide enough for the longest label, whichever table it is in.
+        let width = [removed_label, KEPT_LABEL, HELD_BACK_LABEL, ERRORS_LABEL]
+            .into_iter()
+            .chain(self.by_extension.keys().map(String::as_str))
+            .chain(AGE_BUCKETS.iter().map(|(_, label)| *label))
+            .map(|label| label.chars().count())
+            .max()
+            .unwrap_or_default();
+        println!("\n{}", "Summary".cyan());
+        print_row(removed_label, self.removed, width);
+        println!("  {:<width$} {:>6} File(s)", KEPT_LABEL, self.protected);
+        println!(
+            "  {:<width$} {:>6} File(s)",
+            HELD_BACK_LABEL, self.held_back
+        );
+        println!("  {:<width$} {:>6}", ERRORS_LABEL, self.errors);
+        if self.removed.files == 0 {
+            return;
+        }
+
+        println!("{}", "By extension".cyan());
+        let mut extensions: Vec<(&String, &Tally)> = self.by_extension.iter().collect();
+        extensions.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(b.0)));
+        for (extension, tally) in extensions {
+            print_row(extension, *tally, width);
+        }
+        println!("{}", "By age".cyan());
+        for ((_, label), tally) in AGE_BUCKETS.iter().zip(self.by_age) {
+            if tally.files > 0 {
+                print_row(label, tally, width);
+            }
+        }
+    }
+}
+
+fn print_row(label: &str, tally: Tally, width: usize) {
+    println!(
+        "  {:<width$} {:>6} File(s) {:>9}",
+        label,
+        tally.files,
+        format_size(tally.bytes)
+    );
+}
@@ -0,0 +1,217 @@
+// Moves files to the trash following the freedesktop.org Trash specification
+// (https://specifications.freedesktop.org/trash-spec/trashspec-latest.html)
+// so desktop file managers can restore them.
+use crate::xdg;
+use chrono::Local;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+const TRASH_INFO_DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+const STICKY_BIT: u32 = 0o1000;
+
+// A trash directory together with how the original path is recorded in it.
+struct TrashDir {
+    root: PathBuf,
+    // Top directory of the mount for per-mount trash directories, whose
+    // .trashinfo paths are relative to it. None for the home trash.
+    top_dir: Option<PathBuf>,
+}
+
+// Moves `file` to the trash and returns where it ended up.
+pub fn move_to_trash(file: &Path) -> io::Result<PathBuf> {
+    let file = absolute_path(file)?;
+    let file_dev = fs::symlink_metadata(&file)?.dev();
+
+    let home_trash = home_trash_dir()?;
+    let trash = if device_of_existing_ancestor(&home_trash)? == file_dev {
+        TrashDir {
+            root: home_trash,
+            top_dir: None,
+        }
+    } else {
+        mount_trash_dir(&file, file_dev)?
+    };
+    trash_into(&file, &trash)
+}
+
+fn trash_into(file: &Path, trash: &TrashDir) -> io::Result<PathBuf> {
+    let files_dir = trash.root.join("files");
+    let info_dir = trash.root.join("info");
+    create_private_dir(&files_dir)?;
+    create_private_dir(&info_dir)?;
+
+    let recorded_path = match &trash.top_dir {
+        Some(top_dir) => file.strip_prefix(top_dir).unwrap_or(file),
+        None => file,
+    };
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(recorded_path),
+        Local::now().format(TRASH_INFO_DATE_FORMAT)
+    );
+
+    let file_name = file
+        .file_name()
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "path has no file name"))?
+        .to_string_lossy()
+        .to_string();
+
+    // The info file is created exclusively first to reserve the name, as
+    // required by the spec, so two processes cannot pick the same one.
+    for attempt in 0.. {
+        let trash_name = if attempt == 0 {
+            file_name.clone()
+        } else {
+            format!("{}.{}", file_name, attempt)
+        };
+        let info_path = info_dir.join(format!("{}.trashinfo", trash_name));
+        let mut info_file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(info_file) => info_file,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        let trashed_path = files_dir.join(&trash_name);
+        if trashed_path.symlink_metadata().is_ok() {
+            drop(info_file);
+            let _ = fs::remove_file(&info_path);
+            continue;
+        }
+        let moved = info_file
+            .write_all(info.as_bytes())
+            .and_then(|_| fs::rename(file, &trashed_path));
+        if let Err(e) = moved {
+            let _ = fs::remove_file(&info_path);
+            return Err(e);
+        }
+        return Ok(trashed_path);
+    }
+    unreachable!()
+}
+
+fn home_trash_dir() -> io::Result<PathBuf> {
+    Ok(xdg::data_home()?.join("Trash"))
+}
+
+// Picks the trash directory on the file's own mount: $topdir/.Trash/$uid when
+// the administrator has set up a valid shared .Trash, else $topdir/.Trash-$uid.
+fn mount_trash_dir(file: &Path, file_dev: u64) -> io::Result<TrashDir> {
+    let top_dir = mount_top_dir(file, file_dev)?;
+    let uid = current_uid();
+
+    let shared = top_dir.join(".Trash");
+    if let Ok(metadata) = fs::symlink_metadata(&shared) {
+        if metadata.is_dir() && metadata.permissions().mode() & STICKY_BIT != 0 {
+            let root = shared.join(uid.to_string());
+            if create_private_dir(&root).is_ok() {
+                return Ok(TrashDir {
+                    root,
+                    top_dir: Some(top_dir),
+                });
+            }
+        }
+    }
+
+    let root = top_dir.join(format!(".Trash-{}", uid));
+    create_private_dir(&root)?;
+    Ok(TrashDir {
+        root,
+        top_dir: Some(top_dir),
+    })
+}
+
+// Walks up from `file` until the parent is on a different device.
+fn mount_top_dir(file: &Path, file_dev: u64) -> io::Result<PathBuf> {
+    let mut top_dir = file.parent().unwrap_or(file).to_path_buf();
+    while let Some(parent) = top_dir.parent() {
+        if fs::metadata(parent)?.dev() != file_dev {
+            break;
+        }
+        top_dir = parent.to_path_buf();
+    }
+    Ok(top_dir)
+}
+
+// The home trash may not exist yet, so compare using its nearest ancestor.
+fn device_of_existing_ancestor(path: &Path) -> io::Result<u64> {
+    let mut current = path;
+    loop {
+        match fs::metadata(current) {
+            Ok(metadata) => return Ok(metadata.dev()),
+            Err(e) if e.kind() == ErrorKind::NotFound => match current.parent() {
+                Some(parent) => current = parent,
+                None => return Err(e),
+            },
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    if dir.is_dir() {
+        return Ok(());
+    }
+    fs::create_dir_all(dir)?;
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+}
+
+fn absolute_path(path: &Path) -> io::Result<PathBuf> {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()?.join(path)
+    };
+    // Resolve the parent only, so a symlink is trashed as the link itself.
+    let parent = match path.parent() {
+        Some(parent) if parent.components().next().is_some() => fs::canonicalize(parent)?,
+        _ => PathBuf::from("/"),
+    };
+    match path.file_name() {
+        Some(name) => Ok(parent.join(name)),
+        None => Ok(parent),
+    }
+}
+
+fn current_uid() -> u32 {
+    // SAFETY: getuid has no preconditions and cannot fail.
+    unsafe { libc::getuid() }
+}
+
+// Encodes a path the way the spec requires for the Path= key (RFC 2396).
+fn percent_encode(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.!~*'()".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_encode_escapes_reserved_bytes() {
+        assert_eq!(
+            percent_encode(Path::new("/tmp/a-b_c.txt")),
+            "/tmp/a-b_c.txt"
+        );
+        assert_eq!(
+            percent_encode(Path::new("/tmp/my file#1%.txt")),
+            "/tmp/my%20file%231%25.txt"
+        );
+        assert_eq!(percent_encode(Path::new("/tmp/über")), "/tmp/%C3%BCber");
+    }
+}
@@ -0,0 +1,468 @@
+// Interactive picker for the files to remove, shown instead of the DEL prompt
+// when --interactive is given.
+use crate::age_in_days;
+use crate::listing::SortKey;
+use chrono::{DateTime, Local};
+use clndir::age::DATE_FORMAT;
+use clndir::planner::Plan;
+use clndir::size;
+use clndir::TimeField;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, TableState, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::SystemTime;
+
+// Bytes read from a file for the preview pane.
+const PREVIEW_BYTES: u64 = 16 * 1024;
+const PAGE_SIZE: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Screen {
+    List,
+    Filter,
+    Confirm,
+}
+
+struct Item {
+    group: usize,
+    index: usize,
+    // The directory joined with the relative path, as shown and filtered on.
+    path: String,
+    // Where the file is on disk, for the preview.
+    location: String,
+    extension: String,
+    size: u64,
+    time: SystemTime,
+    ticked: bool,
+    // Why the file is left in place, for files that are held back. These are
+    // listed but cannot be ticked.
+    held_back: Option<String>,
+}
+
+struct Picker {
+    items: Vec<Item>,
+    // Indexes into `items` that pass the filter, in display order.
+    visible: Vec<usize>,
+    table: TableState,
+    sort: SortKey,
+    reversed: bool,
+    filter: String,
+    screen: Screen,
+    preview: bool,
+}
+
+// Shows the candidates and returns, for every directory of the plan, which
+// of its candidates were ticked. None means the user cancelled.
+pub fn pick_files(plan: &Plan, time_field: TimeField) -> io::Result<Option<Vec<Vec<bool>>>> {
+    let mut picker = Picker::new(plan, time_field);
+    let mut terminal = ratatui::try_init()?;
+    let result = picker.run(&mut terminal);
+    ratatui::try_restore()?;
+
+    let confirmed = match result? {
+        true => picker,
+        false => return Ok(None),
+    };
+    let mut ticked: Vec<Vec<bool>> = plan
+        .dirs
+        .iter()
+        .map(|dir| vec![false; dir.candidates.len()])
+        .collect();
+    for item in confirmed
+        .items
+        .iter()
+        .filter(|item| item.held_back.is_none())
+    {
+        ticked[item.group][item.index] = item.ticked;
+    }
+    Ok(Some(ticked))
+}
+
+impl Picker {
+    fn new(plan: &Plan, time_field: TimeField) -> Picker {
+        let mut items = Vec::new();
+        for (group_index, group) in plan.dirs.iter().enumerate() {
+            let candidates = group.candidates.iter().map(|candidate| (candidate, None));
+            let held_back = group
+                .held_back
+                .iter()
+                .map(|candidate| (candidate, Some(candidate.reason.clone())));
+            for (index, (candidate, held_back)) in candidates.chain(held_back).enumerate() {
+                let file = &candidate.file;
+                let path = if plan.dirs.len() > 1 {
+                    format!("{}/{}", group.dir.trim_end_matches('/'), file.name)
+                } else {
+                    file.name.clone()
+                };
+                items.push(Item {
+                    group: group_index,
+                    index,
+                    path,
+                    location: Path::new(&group.dir)
+                        .join(&file.name)
+                        .to_string_lossy()
+                        .to_string(),
+                    extension: Path::new(&file.name)
+                        .extension()
+                        .map(|ext| ext.to_string_lossy().to_lowercase())
+                        .unwrap_or_default(),
+                    size: file.size,
+                    time: file.time(time_field),
+                    ticked: held_back.is_none(),
+                    held_back,
+                });
+            }
+        }
+        let mut picker = Picker {
+            items,
+            visible: Vec::new(),
+            table: TableState::default(),
+            sort: SortKey::Age,
+            reversed: false,
+            filter: String::new(),
+            screen: Screen::List,
+            preview: false,
+        };
+        picker.refresh();
+        picker
+    }
+
+    // Returns true when the user confirmed the selection.
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<bool> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                if let Some(confirmed) = self.handle_key(key) {
+                    return Ok(confirmed);
+                }
+            }
+        }
+    }
+
+    // Returns Some when the picker should close.
+    fn handle_key(&mut self, key: KeyEvent) -> Option<bool> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Some(false);
+        }
+        match self.screen {
+            Screen::Confirm => match key.code {
+                KeyCode::Char('y') | KeyCode::Enter => return Some(true),
+                KeyCode::Char('n') | KeyCode::Esc | KeyCode::Char('q') => {
+                    self.screen = Screen::List
+                }
+                _ => {}
+            },
+            Screen::Filter => match key.code {
+                KeyCode::Enter => self.screen = Screen::List,
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.screen = Screen::List;
+                    self.refresh();
+                }
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                    self.refresh();
+                }
+                KeyCode::Char(c) => {
+                    self.filter.push(c);
+                    self.refresh();
+                }
+                _ => {}
+            },
+            Screen::List => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Some(false),
+                KeyCode::Enter if self.items.iter().any(|item| item.ticked) => {
+                    self.screen = Screen::Confirm
+                }
+                KeyCode::Down | KeyCode::Char('j') => self.move_cursor(1),
+                KeyCode::Up | KeyCode::Char('k') => self.move_cursor(-1),
+                KeyCode::PageDown => self.move_cursor(PAGE_SIZE as isize),
+                KeyCode::PageUp => self.move_cursor(-(PAGE_SIZE as isize)),
+                KeyCode::Home | KeyCode::Char('g') => self.table.select_first(),
+                KeyCode::End | KeyCode::Char('G') => self.table.select_last(),
+                KeyCode::Char(' ') => {
+                    if let Some(item) = self.current() {
+                        let item = &mut self.items[item];
+                        item.ticked = !item.ticked && item.held_back.is_none();
+                    }
+                    self.move_cursor(1);
+                }
+                KeyCode::Char('a') => self.toggle_visible(|_| true),
+                KeyCode::Char('e') => {
+                    if let Some(item) = self.current() {
+                        let extension = self.items[item].extension.clone();
+                        self.toggle_visible(|item| item.extension == extension);
+                    }
+                }
+                KeyCode::Char('s') => {
+                    self.sort = self.sort.next();
+                    self.refresh();
+                }
+                KeyCode::Char('r') => {
+                    self.reversed = !self.reversed;
+                    self.refresh();
+                }
+                KeyCode::Char('/') => self.screen = Screen::Filter,
+                KeyCode::Char('p') | KeyCode::Tab => self.preview = !self.preview,
+                _ => {}
+            },
+        }
+        None
+    }
+
+    fn current(&self) -> Option<usize> {
+        self.table
+            .selected()
+            .and_then(|row| self.visible.get(row).copied())
+    }
+
+    fn move_cursor(&mut self, by: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let row = self.table.selected().unwrap_or(0) as isize + by;
+        let last = self.visible.len() as isize - 1;
+        self.table.select(Some(row.clamp(0, last) as usize));
+    }
+
+    // Ticks every visible item matching `matches`, or unticks them all if
+    // they were all ticked already.
+    fn toggle_visible<F: Fn(&Item) -> bool>(&mut self, matches: F) {
+        let targets: Vec<usize> = self
+            .visible
+            .iter()
+            .copied()
+            .filter(|&item| self.items[item].held_back.is_none() && matches(&self.items[item]))
+            .collect();
+        let tick = !targets.iter().all(|&item| self.items[item].ticked);
+        for item in targets {
+            self.items[item].ticked = tick;
+        }
+    }
+
+    // Reapplies the filter and sort, keeping the cursor on the same file.
+    fn refresh(&mut self) {
+        let current = self.current();
+        let filter = self.filter.to_lowercase();
+        self.visible = (0..self.items.len())
+            .filter(|&item| fuzzy_match(&filter, &self.items[item].path.to_lowercase()))
+            .collect();
+
+        let items = &self.items;
+        self.visible.sort_by(|&a, &b| {
+            let (a, b) = (&items[a], &items[b]);
+            let order = match self.sort {
+                SortKey::Age => a.time.cmp(&b.time),
+                SortKey::Size => b.size.cmp(&a.size),
+                SortKey::Name => a.path.cmp(&b.path),
+                SortKey::Ext => a.extension.cmp(&b.extension).then(a.path.cmp(&b.path)),
+            };
+            if self.reversed {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+
+        let row = current
+            .and_then(|item| self.visible.iter().position(|&visible| visible == item))
+            .unwrap_or(0);
+        self.table.select(if self.visible.is_empty() {
+            None
+        } else {
+            Some(row)
+        });
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let (ticked, bytes) = self.ticked_totals();
+        let held_back = self
+            .items
+            .iter()
+            .filter(|item| item.held_back.is_some())
+            .count();
+        frame.render_widget(
+            Paragraph::new(Line::from(vec![
+                Span::styled("clndir ", Style::new().add_modifier(Modifier::BOLD)),
+                Span::raw(format!(
+                    "{} of {} file(s) ticked, {}{} - sort: {}{} - filter: ",
+                    ticked,
+                    self.items.len() - held_back,
+                    size::format_size(bytes),
+                    match held_back {
+                        0 => String::new(),
+                        count => format!(", {} held back", count),
+                    },
+                    self.sort.label(),
+                    if self.reversed { " (reversed)" } else { "" },
+                )),
+                Span::styled(self.filter.clone(), Style::new().fg(Color::Yellow)),
+            ])),
+            header,
+        );
+
+        let list_area = if self.preview {
+            let [list, preview] =
+                Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)])
+                    .areas(body);
+            self.draw_preview(frame, preview);
+            list
+        } else {
+            body
+        };
+        self.draw_list(frame, list_area);
+
+        let help = match self.screen {
+            Screen::List => "space tick  a all  e extension  / filter  s sort  r reverse  p preview  enter delete  q cancel",
+            Screen::Filter => "type to filter  enter keep  esc clear",
+            Screen::Confirm => "y confirm  n back",
+        };
+        frame.render_widget(
+            Paragraph::new(help).style(Style::new().fg(Color::DarkGray)),
+            footer,
+        );
+
+        if self.screen == Screen::Confirm {
+            self.draw_confirm(frame, ticked, bytes);
+        }
+    }
+
+    fn draw_list(&mut self, frame: &mut Frame, area: Rect) {
+        let rows = self.visible.iter().map(|&item| {
+            let item = &self.items[item];
+            let date = DateTime::<Local>::from(item.time).format(DATE_FORMAT);
+            let mut path = vec![Span::styled(
+                item.path.clone(),
+                Style::new().fg(Color::Yellow),
+            )];
+            if let Some(reason) = &item.held_back {
+                path.push(Span::styled(
+                    format!(" held back, {}", reason),
+                    Style::new().fg(Color::Red),
+                ));
+            }
+            Row::new(vec![
+                Cell::from(match (&item.held_back, item.ticked) {
+                    (Some(_), _) => " - ",
+                    (None, true) => "[x]",
+                    (None, false) => "[ ]",
+                }),
+                Cell::from(size::format_size(item.size)),
+                Cell::from(format!("{}d", age_in_days(item.time))),
+                Cell::from(date.to_string()).style(Style::new().fg(Color::Green)),
+                Cell::from(Line::from(path)),
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(3),
+                Constraint::Length(8),
+                Constraint::Length(6),
+                Constraint::Length(10),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(
+            Row::new(vec!["", "Size", "Age", "Date", "Path"])
+                .style(Style::new().add_modifier(Modifier::BOLD)),
+        )
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+        .block(Block::new().borders(Borders::TOP));
+        frame.render_stateful_widget(table, area, &mut self.table);
+    }
+
+    fn draw_preview(&mut self, frame: &mut Frame, area: Rect) {
+        let text = match self.current() {
+            Some(item) => preview_text(&self.items[item].location),
+            None => String::new(),
+        };
+        frame.render_widget(
+            Paragraph::new(text).wrap(Wrap { trim: false }).block(
+                Block::new()
+                    .borders(Borders::TOP | Borders::LEFT)
+                    .title("Preview"),
+            ),
+            area,
+        );
+    }
+
+    fn draw_confirm(&self, frame: &mut Frame, ticked: usize, bytes: u64) {
+        let area = centered(frame.area(), 50, 5);
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(vec![
+                Line::from(format!("Remove {} file(s)?", ticked)),
+                Line::from(format!("{} will be freed.", size::format_size(bytes))),
+                Line::from(Span::styled(
+                    "y to confirm, n to go back",
+                    Style::new().add_modifier(Modifier::BOLD),
+                )),
+            ])
+            .block(
+                Block::bordered()
+                    .title("Confirm")
+                    .border_style(Style::new().fg(Color::Red)),
+            ),
+            area,
+        );
+    }
+
+    fn ticked_totals(&self) -> (usize, u64) {
+        self.items
+            .iter()
+            .filter(|item| item.ticked)
+            .fold((0, 0), |(count, bytes), item| {
+                (count + 1, bytes + item.size)
+            })
+    }
+}
+
+// Every character of the filter must appear in the path, in order.
+fn fuzzy_match(filter: &str, path: &str) -> bool {
+    let mut path = path.chars();
+    filter.chars().all(|c| path.any(|p| p == c))
+}
+
+// The first part of a file for the preview pane, or why it cannot be shown.
+fn preview_text(path: &str) -> String {
+    let mut buffer = Vec::new();
+    let read = File::open(path).and_then(|file| file.take(PREVIEW_BYTES).read_to_end(&mut buffer));
+    match read {
+        Err(e) => format!("Cannot read file: {}", e),
+        Ok(_) if buffer.contains(&0) => "Binary file, no preview".to_string(),
+        Ok(_) => String::from_utf8_lossy(&buffer).to_string(),
+    }
+}
+
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let [_, row, _] = Layout::vertical([
+        Constraint::Fill(1),
+        Constraint::Length(height),
+        Constraint::Fill(1),
+    ])
+    .areas(area);
+    let [_, cell, _] = Layout::horizontal([
+        Constraint::Fill(1),
+        Constraint::Length(width),
+        Constraint::Fill(1),
+    ])
+    .areas(row);
+    cell
+}
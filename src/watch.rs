@@ -0,0 +1,366 @@
+// `clndir watch`: removes each file as soon as it crosses --age instead of
+// waiting for the next cron run. Every file gets a timer from its timestamp,
+// inotify keeps the timers current as files come and go, and a directory is
+// planned and cleaned again whenever one of its timers fires.
+use crate::config::{Config, Profile};
+use crate::{clean_options, target_dirs, CleanArgs, CleanOptions, Outcome};
+use chrono::{DateTime, Duration, Local};
+use clndir::clndirignore::IGNORE_FILE_NAME;
+use clndir::inotify::{
+    self, Inotify, IN_ATTRIB, IN_CLOSE_WRITE, IN_CREATE, IN_DELETE, IN_IGNORED, IN_ISDIR,
+    IN_MOVED_FROM, IN_MOVED_TO, IN_Q_OVERFLOW,
+};
+use clndir::planner::Plan;
+use clndir::{Action, ClndirError, Executor, FileRecord, Planner, Scanner};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::low_level::pipe;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::{self, Read};
+use std::os::fd::AsFd;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+const LOG_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+// How long a file held open by a process is left before it is tried again.
+const IN_USE_RETRY_SECS: i64 = 60;
+// Changes that can make a file appear, disappear or get a new timestamp.
+// Writes that are still going on are caught when the timer fires, since the
+// directory is scanned again then.
+const WATCH_MASK: u32 =
+    IN_CREATE | IN_CLOSE_WRITE | IN_ATTRIB | IN_MOVED_TO | IN_MOVED_FROM | IN_DELETE;
+
+struct Watcher {
+    options: CleanOptions,
+    dirs: Vec<String>,
+    inotify: Inotify,
+    // The cleaned directory, by index into `dirs`, and the subdirectory
+    // each watch descriptor is on.
+    watches: HashMap<i32, (usize, PathBuf)>,
+    // When each file crosses the age, by cleaned directory and relative path.
+    timers: HashMap<(usize, String), DateTime<Local>>,
+}
+
+// Runs until SIGTERM or SIGINT. SIGHUP reloads the profile from the config
+// file and starts over with a fresh scan.
+pub fn watch(args: &CleanArgs, profile_name: Option<&str>) -> Result<Outcome, ClndirError> {
+    let signal_error = |e: io::Error| ClndirError::Io("signal handler".to_string(), e);
+    let (reload, reload_writer) = UnixStream::pair().map_err(signal_error)?;
+    let (stop, stop_writer) = UnixStream::pair().map_err(signal_error)?;
+    pipe::register(SIGHUP, reload_writer).map_err(signal_error)?;
+    pipe::register(SIGTERM, stop_writer.try_clone().map_err(signal_error)?)
+        .map_err(signal_error)?;
+    pipe::register(SIGINT, stop_writer).map_err(signal_error)?;
+    reload.set_nonblocking(true).map_err(signal_error)?;
+
+    let mut watcher = Watcher::start(args, profile_name)?;
+    loop {
+        let timeout = watcher
+            .next_deadline()
+            .map(|deadline| (deadline - Local::now()).to_std().unwrap_or_default());
+        let ready = inotify::poll_readable(
+            &[watcher.inotify.as_fd(), reload.as_fd(), stop.as_fd()],
+            timeout,
+        )
+        .map_err(|e| ClndirError::Io("inotify".to_string(), e))?;
+        if ready[2] {
+            log("Stopping");
+            return Ok(Outcome::Done);
+        }
+        if ready[1] {
+            // Several signals may have queued up, one reload covers them.
+            let _ = (&reload).read(&mut [0; 64]);
+            log("Reloading configuration");
+            match Watcher::start(args, profile_name) {
+                Ok(reloaded) => watcher = reloaded,
+                Err(e) => log(&format!("Error reloading, keeping the old settings: {}", e)),
+            }
+            continue;
+        }
+        if ready[0] {
+            watcher.handle_events();
+        }
+        watcher.expire_due();
+    }
+}
+
+impl Watcher {
+    fn start(args: &CleanArgs, profile_name: Option<&str>) -> Result<Watcher, ClndirError> {
+        let config = match profile_name {
+            Some(_) => Config::load().map_err(|e| ClndirError::Config(e.to_string()))?,
+            None => Config::default(),
+        };
+        let default_profile = Profile::default();
+        let profile = match profile_name {
+            Some(name) => config
+                .profile(name)
+                .map_err(|e| ClndirError::Config(e.to_string()))?,
+            None => &default_profile,
+        };
+        let dirs = target_dirs(args, profile)?;
+        let options = clean_options(args, profile, None)?;
+
+        let policy = &options.policy;
+        if policy.quota.is_some() || policy.free_space.is_some() || policy.retention.is_some() {
+            return Err(ClndirError::InvalidArgument(
+                "watch removes files by age, without size, free space or --keep-* options"
+                    .to_string(),
+            ));
+        }
+        if policy.window.older_than.is_none() {
+            return Err(ClndirError::InvalidArgument(
+                "watch needs an --age".to_string(),
+            ));
+        }
+        if options.dry_run || options.interactive {
+            return Err(ClndirError::InvalidArgument(
+                "watch cannot be used with --dry-run or --interactive".to_string(),
+            ));
+        }
+
+        log(&format!(
+            "Watching {} for files {}",
+            dirs.join(", "),
+            policy.window.describe()
+        ));
+        let mut watcher = Watcher {
+            options,
+            dirs,
+            inotify: Inotify::new().map_err(|e| ClndirError::Io("inotify".to_string(), e))?,
+            watches: HashMap::new(),
+            timers: HashMap::new(),
+        };
+        for index in 0..watcher.dirs.len() {
+            watcher.sweep(index)?;
+        }
+        Ok(watcher)
+    }
+
+    fn next_deadline(&self) -> Option<DateTime<Local>> {
+        self.timers.values().min().copied()
+    }
+
+    // Scans a cleaned directory afresh, removes what is due, and sets the
+    // timers of the files that remain.
+    fn sweep(&mut self, index: usize) -> Result<(), ClndirError> {
+        self.timers.retain(|(dir, _), _| *dir != index);
+        let dir = self.dirs[index].clone();
+        self.add_watches(index, Path::new(""))
+            .map_err(|e| ClndirError::from_io(&dir, e))?;
+
+        let policy = &self.options.policy;
+        let scan = self.scanner().scan(&dir)?;
+        let now = Local::now();
+        // Files already past their age are removed below, or are protected
+        // and must not fire again.
+        for file in &scan.files {
+            match self.expiry(file) {
+                Some(expiry) if expiry > now => {
+                    self.timers.insert((index, file.name.clone()), expiry);
+                }
+                _ => {}
+            }
+        }
+        let directory_plan = Planner::new(policy).plan_scan(scan, None);
+        for warning in &directory_plan.warnings {
+            log(&warning.to_string());
+        }
+        // Nothing may tell when a reader lets go of the file, so it is
+        // tried again after a while.
+        for held_back in &directory_plan.held_back {
+            let name = &held_back.file.name;
+            log(&format!(
+                "Held back {}: {}",
+                Path::new(&dir).join(name).display(),
+                held_back.reason
+            ));
+            self.timers.insert(
+                (index, name.clone()),
+                now + Duration::seconds(IN_USE_RETRY_SECS),
+            );
+        }
+        if !directory_plan.candidates.is_empty() {
+            self.execute(Plan {
+                dirs: vec![directory_plan],
+                skipped: Vec::new(),
+            });
+        }
+        Ok(())
+    }
+
+    fn sweep_or_log(&mut self, index: usize) {
+        if let Err(e) = self.sweep(index) {
+            log(&format!("Error: {}", e));
+        }
+    }
+
+    // Watches `relative` and the subdirectories below it that can hold files
+    // within --max-depth. Symlinked directories are not followed.
+    fn add_watches(&mut self, index: usize, relative: &Path) -> io::Result<()> {
+        let path = Path::new(&self.dirs[index]).join(relative);
+        let wd = self.inotify.add_watch(&path, WATCH_MASK)?;
+        self.watches.insert(wd, (index, relative.to_path_buf()));
+
+        // Files in `relative` are one level deeper than the directory itself.
+        let file_depth = relative.components().count() + 1;
+        if file_depth >= self.options.policy.depth.max {
+            return Ok(());
+        }
+        for entry in fs::read_dir(&path)?.flatten() {
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                let subdirectory = relative.join(entry.file_name());
+                if let Err(e) = self.add_watches(index, &subdirectory) {
+                    log(&format!(
+                        "Error watching {}: {}",
+                        path.join(entry.file_name()).display(),
+                        e
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_events(&mut self) {
+        let events = match self.inotify.read_events() {
+            Ok(events) => events,
+            Err(e) => {
+                log(&format!("Error reading inotify events: {}", e));
+                return;
+            }
+        };
+        let mut to_sweep = BTreeSet::new();
+        for event in events {
+            if event.mask & IN_Q_OVERFLOW != 0 {
+                log("Missed some changes, scanning again");
+                to_sweep.extend(0..self.dirs.len());
+                continue;
+            }
+            if event.mask & IN_IGNORED != 0 {
+                self.watches.remove(&event.wd);
+                continue;
+            }
+            let (Some((index, relative_dir)), Some(name)) =
+                (self.watches.get(&event.wd).cloned(), event.name)
+            else {
+                continue;
+            };
+            // A new subdirectory or ignore file can change which files are
+            // watched or protected, so the whole directory is scanned again.
+            if event.mask & IN_ISDIR != 0 {
+                if event.mask & (IN_CREATE | IN_MOVED_TO) != 0 {
+                    to_sweep.insert(index);
+                }
+                continue;
+            }
+            if name == IGNORE_FILE_NAME {
+                to_sweep.insert(index);
+                continue;
+            }
+            let relative = relative_dir.join(&name);
+            if event.mask & (IN_DELETE | IN_MOVED_FROM) != 0 {
+                self.timers
+                    .remove(&(index, relative.to_string_lossy().to_string()));
+            } else {
+                self.update_timer(index, &relative);
+            }
+        }
+        for index in to_sweep {
+            self.sweep_or_log(index);
+        }
+    }
+
+    fn update_timer(&mut self, index: usize, relative: &Path) {
+        let depth = self.options.policy.depth;
+        let file_depth = relative.components().count();
+        if file_depth < depth.min || file_depth > depth.max {
+            return;
+        }
+        let name = relative.to_string_lossy().to_string();
+        let path = Path::new(&self.dirs[index]).join(relative);
+        // A timer already past fires straight away, and the sweep then
+        // decides whether the file really goes.
+        let record = self.scanner().record(&path, name.clone());
+        match record
+            .ok()
+            .flatten()
+            .and_then(|record| self.expiry(&record))
+        {
+            Some(expiry) => self.timers.insert((index, name), expiry),
+            None => self.timers.remove(&(index, name)),
+        };
+    }
+
+    fn expire_due(&mut self) {
+        let now = Local::now();
+        let due: BTreeSet<usize> = self
+            .timers
+            .iter()
+            .filter(|(_, expiry)| **expiry <= now)
+            .map(|((index, _), _)| *index)
+            .collect();
+        for index in due {
+            self.sweep_or_log(index);
+        }
+    }
+
+    fn scanner(&self) -> Scanner {
+        let policy = &self.options.policy;
+        Scanner::new(
+            policy.depth,
+            policy.ignore_files_case_sensitive,
+            policy.file_types,
+        )
+    }
+
+    fn expiry(&self, file: &FileRecord) -> Option<DateTime<Local>> {
+        let policy = &self.options.policy;
+        let age = policy.window.older_than?;
+        Some(age.after(DateTime::from(file.time(policy.time_field))))
+    }
+
+    fn execute(&self, plan: Plan) {
+        let report =
+            Executor::new(self.options.action, self.options.archive.clone()).execute(&plan);
+        let verb = match report.action {
+            Action::Trash => "Trashed",
+            _ => "Deleted",
+        };
+        for dir in &report.dirs {
+            let dir_path = Path::new(&dir.dir);
+            if let Some(e) = &dir.archive_error {
+                log(&format!(
+                    "Error archiving files from {}, nothing was removed: {}",
+                    dir.dir, e
+                ));
+            }
+            if let Some(archive) = &dir.archived_to {
+                log(&format!("Archived to {}", archive.display()));
+            }
+            for removed in &dir.removed {
+                log(&format!(
+                    "{} {}",
+                    verb,
+                    dir_path.join(&removed.file.name).display()
+                ));
+            }
+            for failure in &dir.failed {
+                log(&format!(
+                    "Error removing {}: {}",
+                    dir_path.join(&failure.name).display(),
+                    failure.error
+                ));
+            }
+        }
+        if let Some(run_id) = &report.run_id {
+            log(&format!("Run {} journaled", run_id));
+        }
+        if let Some(e) = &report.journal_error {
+            log(&format!("Error writing journal: {}", e));
+        }
+    }
+}
+
+fn log(message: &str) {
+    println!("{} {}", Local::now().format(LOG_TIME_FORMAT), message);
+}
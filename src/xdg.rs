@@ -0,0 +1,32 @@
+// Base directories from the XDG Base Directory specification.
+use std::env;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+
+// $XDG_CONFIG_HOME, defaulting to ~/.config.
+pub fn config_home() -> io::Result<PathBuf> {
+    base_dir("XDG_CONFIG_HOME", ".config")
+}
+
+// $XDG_DATA_HOME, defaulting to ~/.local/share.
+pub fn data_home() -> io::Result<PathBuf> {
+    base_dir("XDG_DATA_HOME", ".local/share")
+}
+
+// $XDG_STATE_HOME, defaulting to ~/.local/state.
+pub fn state_home() -> io::Result<PathBuf> {
+    base_dir("XDG_STATE_HOME", ".local/state")
+}
+
+fn base_dir(var_name: &str, default_under_home: &str) -> io::Result<PathBuf> {
+    match env::var_os(var_name) {
+        Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir)),
+        _ => match env::var_os("HOME") {
+            Some(home) => Ok(PathBuf::from(home).join(default_under_home)),
+            None => Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("neither {} nor HOME is set", var_name),
+            )),
+        },
+    }
+}
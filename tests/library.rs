@@ -0,0 +1,156 @@
+// The library API as another tool would use it: scan, plan, then execute.
+use clndir::age::{parse_age, TimeWindow};
+use clndir::pattern::Pattern;
+use clndir::policy::{DepthRange, EvictBy, FileTypes, Hidden, SymlinkPolicy};
+use clndir::{Action, CleanupPolicy, Executor, Planner, Scanner, TimeField};
+use std::env;
+use std::fs::{self, File};
+use std::os::unix::fs::symlink;
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, SystemTime};
+
+const DAY: Duration = Duration::from_secs(60 * 60 * 24);
+
+// A fresh directory for one test, removed when it is dropped.
+struct TestDir(PathBuf);
+
+impl TestDir {
+    fn new(name: &str) -> TestDir {
+        let dir = env::temp_dir().join(format!("clndir-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TestDir(dir)
+    }
+
+    fn file(&self, name: &str, days_old: u32) {
+        let path = self.0.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, name).unwrap();
+        let modified = SystemTime::now() - DAY * days_old;
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    fn path(&self) -> String {
+        self.0.to_string_lossy().to_string()
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn policy(age: &str) -> CleanupPolicy {
+    CleanupPolicy {
+        window: TimeWindow {
+            older_than: Some(parse_age(age).unwrap()),
+            ..TimeWindow::default()
+        },
+        time_field: TimeField::Mtime,
+        quota: None,
+        free_space: None,
+        evict_by: EvictBy::Age,
+        retention: None,
+        dedupe: None,
+        skip: Vec::new(),
+        only: Vec::new(),
+        ignore_files_case_sensitive: false,
+        depth: DepthRange { min: 1, max: 1 },
+        file_types: FileTypes::default(),
+    }
+}
+
+fn names(mut names: Vec<&str>) -> Vec<&str> {
+    names.sort();
+    names
+}
+
+#[test]
+fn scanner_records_files_within_depth() {
+    let dir = TestDir::new("scanner");
+    dir.file("a.txt", 1);
+    dir.file(".hidden", 1);
+    dir.file("sub/b.txt", 1);
+    dir.file("sub/deeper/c.txt", 1);
+
+    let file_types = FileTypes {
+        hidden: Hidden::Exclude,
+        ..FileTypes::default()
+    };
+    let scanner = Scanner::new(DepthRange { min: 1, max: 2 }, false, file_types);
+    let scan = scanner.scan(&dir.path()).unwrap();
+    let found = scan.files.iter().map(|file| file.name.as_str()).collect();
+    assert_eq!(names(found), ["a.txt", "sub/b.txt"]);
+    assert!(scan.warnings.is_empty());
+    assert!(scanner.scan(&format!("{}/missing", dir.path())).is_err());
+}
+
+#[test]
+fn followed_links_take_the_times_of_their_target_and_their_own_size() {
+    let dir = TestDir::new("follow");
+    dir.file("target.log", 40);
+    let target = dir.0.join("target.log");
+    symlink(&target, dir.0.join("link")).unwrap();
+
+    let file_types = FileTypes {
+        symlinks: SymlinkPolicy::Follow,
+        ..FileTypes::default()
+    };
+    let scanner = Scanner::new(DepthRange { min: 1, max: 1 }, false, file_types);
+    let scan = scanner.scan(&dir.path()).unwrap();
+    let link = scan.files.iter().find(|file| file.name == "link").unwrap();
+    let target = fs::metadata(&target).unwrap();
+    assert_eq!(link.modified_time, target.modified().unwrap());
+    assert_eq!(
+        link.size,
+        fs::symlink_metadata(dir.0.join("link")).unwrap().len()
+    );
+    assert_ne!(link.size, target.len());
+}
+
+#[test]
+fn planner_picks_old_files_and_reasons() {
+    let dir = TestDir::new("planner");
+    dir.file("old.log", 40);
+    dir.file("new.log", 1);
+    dir.file("keep-old.log", 40);
+
+    let mut policy = policy("30d");
+    policy.skip = vec![Pattern::parse("keep*", false).unwrap()];
+    let plan = Planner::new(&policy).plan(&[dir.path()]).unwrap();
+    assert_eq!(plan.dirs.len(), 1);
+    let candidates = &plan.dirs[0].candidates;
+    let picked = candidates
+        .iter()
+        .map(|candidate| candidate.file.name.as_str())
+        .collect();
+    assert_eq!(names(picked), ["old.log"]);
+    assert_eq!(candidates[0].reason, policy.describe());
+    assert_eq!(plan.protected_count(), 1);
+    assert_eq!(plan.total_size(), "old.log".len() as u64);
+}
+
+#[test]
+fn executor_removes_the_planned_files() {
+    let dir = TestDir::new("executor");
+    dir.file("old.log", 40);
+    dir.file("new.log", 1);
+    // Keeps the journal of the run out of the real state directory.
+    env::set_var("XDG_STATE_HOME", dir.0.join("state"));
+
+    let policy = policy("30d");
+    let plan = Planner::new(&policy).plan(&[dir.path()]).unwrap();
+    let report = Executor::new(Action::Delete, None).execute(&plan);
+    assert_eq!(report.removed_count(), 1);
+    assert_eq!(report.failed_count(), 0);
+    assert_eq!(report.bytes_freed(), "old.log".len() as u64);
+    assert!(!dir.0.join("old.log").exists());
+    assert!(dir.0.join("new.log").exists());
+}